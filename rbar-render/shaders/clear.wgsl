@@ -0,0 +1,14 @@
+// Clears whatever the scissor rect lets through to transparent. A load op clear would ignore
+// the scissor and wipe the whole target.
+
+// One triangle covering the whole target, from the vertex index alone.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(0.0);
+}
@@ -1,9 +1,8 @@
-
 struct VertexIn {
     // 1. The small quad corner position (-0.5 to 0.5)
     @location(0) pos: vec2<f32>,
 
-    // 2. Widget's screen position (in pixels)
+    // 2. Widget's top-left screen position (in pixels)
     @location(1) instance_pos: vec2<f32>,
 
     // 3. Widget's dimensions (width/height in pixels)
@@ -11,12 +10,51 @@ struct VertexIn {
 
     // 4. Widget's color
     @location(3) color: vec4<f32>,
+
+    // 5. Corner radii: top-left, top-right, bottom-right, bottom-left (in pixels)
+    @location(4) radius: vec4<f32>,
+
+    // 6. Border, outline and shadow colors
+    @location(5) border_color: vec4<f32>,
+    @location(6) outline_color: vec4<f32>,
+    @location(7) shadow_color: vec4<f32>,
+
+    // 7. Border width, outline width, outline offset, shadow blur (in pixels)
+    @location(8) params: vec4<f32>,
+
+    // 8. Shadow offset (xy), shadow spread, 1.0 if the shadow is inset
+    @location(9) shadow: vec4<f32>,
+
+    // 9. Fill kind, linear gradient angle, radial gradient center (xy)
+    @location(10) fill: vec4<f32>,
+
+    // 10. Offsets of gradient stops 0 to 7, and their colors as packed sRGB bytes
+    @location(11) stop_offsets_0: vec4<f32>,
+    @location(12) stop_offsets_1: vec4<f32>,
+    @location(13) stop_colors_0: vec4<u32>,
+    @location(14) stop_colors_1: vec4<u32>,
+
+    // 11. Image atlas region (xy origin, zw size, in texels)
+    @location(15) image_rect: vec4<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-    @location(1) widget_color: vec4<f32>,
+    @location(0) local_pos: vec2<f32>,
+    @location(1) @interpolate(flat) widget_color: vec4<f32>,
+    @location(2) @interpolate(flat) fill: vec4<f32>,
+    @location(3) @interpolate(flat) size: vec2<f32>,
+    @location(4) @interpolate(flat) radius: vec4<f32>,
+    @location(5) @interpolate(flat) border_color: vec4<f32>,
+    @location(6) @interpolate(flat) outline_color: vec4<f32>,
+    @location(7) @interpolate(flat) shadow_color: vec4<f32>,
+    @location(8) @interpolate(flat) params: vec4<f32>,
+    @location(9) @interpolate(flat) shadow: vec4<f32>,
+    @location(10) @interpolate(flat) stop_offsets_0: vec4<f32>,
+    @location(11) @interpolate(flat) stop_offsets_1: vec4<f32>,
+    @location(12) @interpolate(flat) stop_colors_0: vec4<u32>,
+    @location(13) @interpolate(flat) stop_colors_1: vec4<u32>,
+    @location(14) @interpolate(flat) image_rect: vec4<f32>,
 };
 
 struct Globals {
@@ -26,16 +64,45 @@ struct Globals {
 @group(0) @binding(0)
 var<uniform> u_globals: Globals;
 
+@group(1) @binding(0)
+var t_image: texture_2d<f32>;
+
+@group(1) @binding(1)
+var s_image: sampler;
+
+const FILL_LINEAR_GRADIENT: f32 = 1.0;
+const FILL_RADIAL_GRADIENT: f32 = 2.0;
+const FILL_IMAGE: f32 = 3.0;
+
+// Extra pixels around each widget so the anti-aliased edge isn't clipped by the quad itself.
+const AA_MARGIN: f32 = 1.0;
+
+// How far outside the widget's edge the outline and an outset shadow can reach.
+fn decoration_extent(in: VertexIn) -> f32 {
+    let outline = select(0.0, max(in.params.z + in.params.y, 0.0), in.params.y > 0.0);
+    let is_outset_shadow = in.shadow_color.a > 0.0 && in.shadow.w == 0.0;
+    let shadow = select(
+        0.0,
+        in.params.w + max(in.shadow.z, 0.0) + max(abs(in.shadow.x), abs(in.shadow.y)),
+        is_outset_shadow,
+    );
+
+    return max(outline, shadow);
+}
+
 @vertex
 fn vs_main(in: VertexIn) -> VertexOutput {
     var out: VertexOutput;
 
-    // 1. SCALING: Scale the quad's corner position by the widget's size.
+    // 1. SCALING: Scale the quad's corner position by the widget's size, plus room for the AA
+    // edge, the outline and the shadow.
     // e.g., corner (0.5, 0.5) * size (100, 50) = (50, 25)
-    let scaled_pos = in.pos * in.size;
+    let margin = AA_MARGIN + decoration_extent(in);
+    let scaled_pos = in.pos * (in.size + 2.0 * margin);
 
-    // 2. TRANSLATION: Move the scaled corner to the widget's screen position.
-    let screen_pos_px = scaled_pos + in.instance_pos;
+    // 2. TRANSLATION: Move the scaled corner to the widget's screen position. The quad is
+    // centered on the origin, so offset it by half the size to anchor it at its top-left.
+    let screen_pos_px = scaled_pos + in.instance_pos + in.size * 0.5;
 
     // 3. NORMALIZATION: Convert screen pixels to Normalized Device Coordinates (NDC: -1.0 to 1.0)
     let clip_pos_xy = (screen_pos_px / u_globals.resolution) * 2.0 - 1.0;
@@ -44,13 +111,180 @@ fn vs_main(in: VertexIn) -> VertexOutput {
     out.clip_position = vec4<f32>(clip_pos_xy.x, clip_pos_xy.y * -1.0, 0.0, 1.0);
 
     // Pass data for fragment shader
-    out.uv = in.pos + 0.5;
     out.widget_color = in.color;
+    out.fill = in.fill;
+    out.local_pos = scaled_pos;
+    out.size = in.size;
+    out.radius = in.radius;
+    out.border_color = in.border_color;
+    out.outline_color = in.outline_color;
+    out.shadow_color = in.shadow_color;
+    out.params = in.params;
+    out.shadow = in.shadow;
+    out.stop_offsets_0 = in.stop_offsets_0;
+    out.stop_offsets_1 = in.stop_offsets_1;
+    out.stop_colors_0 = in.stop_colors_0;
+    out.stop_colors_1 = in.stop_colors_1;
+    out.image_rect = in.image_rect;
 
     return out;
 }
 
+// Signed distance from `p` to a box of half-extent `half` centered on the origin, with each
+// corner rounded by its own radius. Negative inside, positive outside, in pixels.
+fn sd_rounded_box(p: vec2<f32>, half: vec2<f32>, radius: vec4<f32>) -> f32 {
+    // Screen space has y pointing down, so p.y < 0.0 is the top half.
+    let side = select(radius.xw, radius.yz, p.x > 0.0);
+    let r = clamp(select(side.x, side.y, p.y > 0.0), 0.0, min(half.x, half.y));
+    let q = abs(p) - half + r;
+
+    return min(max(q.x, q.y), 0.0) + length(max(q, vec2<f32>(0.0))) - r;
+}
+
+// Anti-aliased coverage of the area where `distance` is negative.
+fn coverage(distance: f32) -> f32 {
+    return clamp(0.5 - distance, 0.0, 1.0);
+}
+
+// Coverage softened over `blur` pixels, approximating a gaussian falloff.
+fn blurred_coverage(distance: f32, blur: f32) -> f32 {
+    let half_blur = max(blur * 0.5, 0.5);
+    return 1.0 - smoothstep(-half_blur, half_blur, distance);
+}
+
+fn premultiply(color: vec4<f32>, alpha: f32) -> vec4<f32> {
+    let a = color.a * alpha;
+    return vec4<f32>(color.rgb * a, a);
+}
+
+// Premultiplied linear color of a gradient stop packed as straight-alpha sRGB bytes.
+fn stop_color(packed: u32) -> vec4<f32> {
+    let srgb = unpack4x8unorm(packed);
+    let linear = select(
+        pow((srgb.rgb + 0.055) / 1.055, vec3<f32>(2.4)),
+        srgb.rgb / 12.92,
+        srgb.rgb <= vec3<f32>(0.04045),
+    );
+    return premultiply(vec4<f32>(linear, srgb.a), 1.0);
+}
+
+// Composites premultiplied `src` over premultiplied `dst`.
+fn over(src: vec4<f32>, dst: vec4<f32>) -> vec4<f32> {
+    return src + dst * (1.0 - src.a);
+}
+
+// Position of the fragment along the gradient line, 0.0 at the start and 1.0 at the end.
+fn gradient_position(in: VertexOutput) -> f32 {
+    if in.fill.x == FILL_RADIAL_GRADIENT {
+        let center = (in.fill.zw - 0.5) * in.size;
+        let farthest = abs(center) + in.size * 0.5;
+        return length(in.local_pos - center) / max(length(farthest), 0.0001);
+    }
+
+    // Same gradient line length as CSS, so the corners land exactly on the first and last stop.
+    let direction = vec2<f32>(sin(in.fill.y), -cos(in.fill.y));
+    let line_length = abs(in.size.x * direction.x) + abs(in.size.y * direction.y);
+    return dot(in.local_pos, direction) / max(line_length, 0.0001) + 0.5;
+}
+
+// Straight-alpha texel of the image stretched over the widget.
+fn image_texel(in: VertexOutput) -> vec4<f32> {
+    let atlas_size = vec2<f32>(textureDimensions(t_image));
+    let local_uv = clamp(in.local_pos / in.size + 0.5, vec2<f32>(0.0), vec2<f32>(1.0));
+    // Stay half a texel inside the region so filtering never reads a neighbouring image.
+    let texel = clamp(
+        in.image_rect.xy + local_uv * in.image_rect.zw,
+        in.image_rect.xy + 0.5,
+        in.image_rect.xy + in.image_rect.zw - 0.5,
+    );
+
+    // The fill kind varies per instance, so an implicit-derivative sample isn't allowed here.
+    return textureSampleLevel(t_image, s_image, texel / atlas_size, 0.0);
+}
+
+// Premultiplied fill color: the image, the gradient blending every pair of stops in turn, or
+// the solid color.
+fn fill_color(in: VertexOutput) -> vec4<f32> {
+    if in.fill.x == FILL_IMAGE {
+        return premultiply(image_texel(in) * in.widget_color, 1.0);
+    }
+
+    if in.fill.x != FILL_LINEAR_GRADIENT && in.fill.x != FILL_RADIAL_GRADIENT {
+        return premultiply(in.widget_color, 1.0);
+    }
+
+    let t = gradient_position(in);
+    let a = in.stop_offsets_0;
+    let b = in.stop_offsets_1;
+    let offsets = array<f32, 8>(a.x, a.y, a.z, a.w, b.x, b.y, b.z, b.w);
+    let c = in.stop_colors_0;
+    let d = in.stop_colors_1;
+    let colors = array<u32, 8>(c.x, c.y, c.z, c.w, d.x, d.y, d.z, d.w);
+
+    var color = stop_color(colors[0]);
+    for (var i = 1; i < 8; i++) {
+        let span = max(offsets[i] - offsets[i - 1], 0.0001);
+        color = mix(color, stop_color(colors[i]), clamp((t - offsets[i - 1]) / span, 0.0, 1.0));
+    }
+
+    return color;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return in.widget_color;
+    let half = in.size * 0.5;
+    let border_width = in.params.x;
+    let outline_width = in.params.y;
+    let outline_offset = in.params.z;
+    let blur = in.params.w;
+    let shadow_offset = in.shadow.xy;
+    let spread = in.shadow.z;
+    let inset = in.shadow.w > 0.5;
+
+    let distance = sd_rounded_box(in.local_pos, half, in.radius);
+    let outer = coverage(distance);
+    // Offsetting the field by the border width also shrinks the corner radii, like CSS does.
+    let inner = coverage(distance + border_width);
+
+    var color = vec4<f32>(0.0);
+
+    // Outset shadows only show outside the widget.
+    if !inset && in.shadow_color.a > 0.0 {
+        let shadow_distance = sd_rounded_box(
+            in.local_pos - shadow_offset,
+            half + spread,
+            in.radius + spread,
+        );
+        color = premultiply(in.shadow_color, blurred_coverage(shadow_distance, blur) * (1.0 - outer));
+    }
+
+    color = over(fill_color(in) * inner, color);
+
+    // Inset shadows fall inside the border, on top of the fill.
+    if inset && in.shadow_color.a > 0.0 {
+        let hole_distance = sd_rounded_box(
+            in.local_pos - shadow_offset,
+            half - border_width - spread,
+            in.radius - border_width - spread,
+        );
+        let shadow = 1.0 - blurred_coverage(hole_distance, blur);
+        color = over(premultiply(in.shadow_color, shadow * inner), color);
+    }
+
+    if border_width > 0.0 {
+        color = over(premultiply(in.border_color, outer - inner), color);
+    }
+
+    if outline_width > 0.0 {
+        let outline_distance = distance - outline_offset;
+        let ring = coverage(outline_distance - outline_width) * (1.0 - coverage(outline_distance));
+        color = over(premultiply(in.outline_color, ring), color);
+    }
+
+    if color.a <= 0.0 {
+        return vec4<f32>(0.0);
+    }
+
+    // The pipeline blends with straight alpha.
+    return vec4<f32>(color.rgb / color.a, color.a);
 }
@@ -0,0 +1,80 @@
+struct VertexIn {
+    // 1. The small quad corner position (-0.5 to 0.5)
+    @location(0) pos: vec2<f32>,
+
+    // 2. Glyph's top-left screen position (in pixels)
+    @location(1) instance_pos: vec2<f32>,
+
+    // 3. Glyph's bitmap dimensions (in pixels)
+    @location(2) size: vec2<f32>,
+
+    // 4. Glyph's top-left corner in the atlas (in texels)
+    @location(3) uv: vec2<f32>,
+
+    // 5. Text color, ignored for color glyphs such as emoji
+    @location(4) color: vec4<f32>,
+
+    // 6. Non-zero when the atlas holds the glyph's own colors
+    @location(5) is_color: u32,
+
+    // 7. Non-zero when the glyph is turned 90 degrees clockwise
+    @location(6) rotated: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) is_color: u32,
+};
+
+struct Globals {
+    resolution: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> u_globals: Globals;
+
+@group(1) @binding(0)
+var t_atlas: texture_2d<f32>;
+
+@group(1) @binding(1)
+var s_atlas: sampler;
+
+@vertex
+fn vs_main(in: VertexIn) -> VertexOutput {
+    var out: VertexOutput;
+
+    // Glyphs are positioned by their top-left corner, so shift the corner into 0.0 to 1.0.
+    let corner = in.pos + 0.5;
+    var screen_size = in.size;
+    var texel_corner = corner;
+
+    // A rotated glyph covers its bitmap turned on its side: the screen's left edge shows the
+    // bitmap's bottom row and the screen's top edge its left column.
+    if in.rotated != 0u {
+        screen_size = in.size.yx;
+        texel_corner = vec2<f32>(corner.y, 1.0 - corner.x);
+    }
+
+    let screen_pos_px = in.instance_pos + corner * screen_size;
+    let clip_pos_xy = (screen_pos_px / u_globals.resolution) * 2.0 - 1.0;
+
+    out.clip_position = vec4<f32>(clip_pos_xy.x, clip_pos_xy.y * -1.0, 0.0, 1.0);
+    out.uv = (in.uv + texel_corner * in.size) / vec2<f32>(textureDimensions(t_atlas));
+    out.color = in.color;
+    out.is_color = in.is_color;
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = textureSample(t_atlas, s_atlas, in.uv);
+
+    if in.is_color != 0u {
+        return texel;
+    }
+
+    return vec4<f32>(in.color.rgb, in.color.a * texel.a);
+}
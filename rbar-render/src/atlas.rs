@@ -0,0 +1,333 @@
+use etagere::{BucketedAtlasAllocator, size2};
+use wgpu::{
+    CommandEncoderDescriptor, Device, Extent3d, Origin3d, Queue, TexelCopyBufferLayout,
+    TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView,
+};
+
+/// Gap left around every allocation so linear sampling never bleeds into a neighbour.
+const PADDING: u32 = 1;
+
+/// A region of the atlas texture, in texels.
+#[derive(Copy, Clone, Debug)]
+pub struct AtlasRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Rectangle packing for a square atlas, independent of where the pixels are stored.
+pub struct Packer {
+    allocator: BucketedAtlasAllocator,
+    size: u32,
+}
+
+/// An RGBA texture packed with an etagere allocator.
+///
+/// The atlas doubles in size when it runs out of room; `generation` is bumped every time the
+/// texture is replaced so that bind groups pointing at the old view can be rebuilt.
+pub struct Atlas {
+    label: &'static str,
+    texture: Texture,
+    view: TextureView,
+    packer: Packer,
+    generation: u64,
+}
+
+impl Packer {
+    pub fn new(size: u32) -> Self {
+        Self {
+            allocator: BucketedAtlasAllocator::new(size2(size as i32, size as i32)),
+            size,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Reserves a `width` x `height` region, or returns `None` if the atlas needs to grow.
+    pub fn allocate(&mut self, width: u32, height: u32) -> Option<AtlasRegion> {
+        let padded = size2((width + PADDING * 2) as i32, (height + PADDING * 2) as i32);
+        let min = self.allocator.allocate(padded)?.rectangle.min;
+
+        Some(AtlasRegion {
+            x: min.x as u32 + PADDING,
+            y: min.y as u32 + PADDING,
+            width,
+            height,
+        })
+    }
+
+    /// Grows the atlas to `size`, keeping every existing region where it is.
+    pub fn grow(&mut self, size: u32) {
+        self.allocator.grow(size2(size as i32, size as i32));
+        self.size = size;
+    }
+}
+
+impl Atlas {
+    pub fn new(device: &Device, label: &'static str, size: u32) -> Self {
+        let texture = Self::create_texture(device, label, size);
+        let view = texture.create_view(&Default::default());
+
+        Self {
+            label,
+            texture,
+            view,
+            packer: Packer::new(size),
+            generation: 0,
+        }
+    }
+
+    fn create_texture(device: &Device, label: &'static str, size: u32) -> Texture {
+        device.create_texture(&TextureDescriptor {
+            label: Some(label),
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            // Uploads are sRGB encoded like the surface, so sampling hands the shaders the same
+            // linear values they blend everything else in.
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Reserves a `width` x `height` region, growing the texture if needed.
+    ///
+    /// Returns `None` only when the atlas is already as large as the device allows.
+    pub fn allocate(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+    ) -> Option<AtlasRegion> {
+        loop {
+            if let Some(region) = self.packer.allocate(width, height) {
+                return Some(region);
+            }
+
+            if !self.grow(device, queue) {
+                return None;
+            }
+        }
+    }
+
+    fn grow(&mut self, device: &Device, queue: &Queue) -> bool {
+        let max = device.limits().max_texture_dimension_2d;
+        let size = self.packer.size();
+
+        if size >= max {
+            log::warn!("{} is full at {size}x{size}", self.label);
+            return false;
+        }
+
+        let new_size = (size * 2).min(max);
+        let texture = Self::create_texture(device, self.label, new_size);
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Atlas grow encoder"),
+        });
+        encoder.copy_texture_to_texture(
+            self.texture.as_image_copy(),
+            texture.as_image_copy(),
+            Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit([encoder.finish()]);
+
+        self.packer.grow(new_size);
+        self.view = texture.create_view(&Default::default());
+        self.texture = texture;
+        self.generation += 1;
+
+        true
+    }
+
+    /// Uploads tightly packed RGBA8 pixels into `region`.
+    pub fn upload(&self, queue: &Queue, region: AtlasRegion, data: &[u8]) {
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: region.x,
+                    y: region.y,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            data,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(region.width * 4),
+                rows_per_image: Some(region.height),
+            },
+            Extent3d {
+                width: region.width,
+                height: region.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use wgpu::{
+        BufferDescriptor, BufferUsages, COPY_BYTES_PER_ROW_ALIGNMENT, DeviceDescriptor, Instance,
+        MapMode, PollType, RequestAdapterOptions, TexelCopyBufferInfo,
+    };
+
+    use super::*;
+
+    fn overlaps(a: &AtlasRegion, b: &AtlasRegion) -> bool {
+        a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+    }
+
+    /// Fills `packer` with `size` x `size` regions until it has no room left.
+    fn fill(packer: &mut Packer, size: u32, regions: &mut Vec<AtlasRegion>) {
+        while let Some(region) = packer.allocate(size, size) {
+            regions.push(region);
+        }
+    }
+
+    /// A device on the fallback adapter, or `None` on machines without one.
+    async fn device() -> Option<(Device, Queue)> {
+        let adapter = Instance::default()
+            .request_adapter(&RequestAdapterOptions {
+                force_fallback_adapter: true,
+                ..Default::default()
+            })
+            .await
+            .ok()?;
+
+        adapter
+            .request_device(&DeviceDescriptor::default())
+            .await
+            .ok()
+    }
+
+    fn read_texel(device: &Device, queue: &Queue, atlas: &Atlas, x: u32, y: u32) -> [u8; 4] {
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            TexelCopyTextureInfo {
+                texture: &atlas.texture,
+                mip_level: 0,
+                origin: Origin3d { x, y, z: 0 },
+                aspect: TextureAspect::All,
+            },
+            TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: Some(1),
+                },
+            },
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(PollType::wait_indefinitely()).unwrap();
+        receiver.recv().unwrap().unwrap();
+
+        slice.get_mapped_range()[..4].try_into().unwrap()
+    }
+
+    #[test]
+    fn regions_are_padded_and_disjoint() {
+        let mut packer = Packer::new(64);
+        let mut regions = vec![];
+        fill(&mut packer, 14, &mut regions);
+
+        assert!(regions.len() > 1);
+        for (i, a) in regions.iter().enumerate() {
+            assert!(a.x >= PADDING && a.y >= PADDING);
+            assert!(a.x + a.width + PADDING <= 64 && a.y + a.height + PADDING <= 64);
+            assert!(regions[i + 1..].iter().all(|b| !overlaps(a, b)));
+        }
+    }
+
+    #[test]
+    fn grows_past_initial_size() {
+        let mut packer = Packer::new(64);
+        let mut regions = vec![];
+        fill(&mut packer, 30, &mut regions);
+        let before = regions.len();
+        assert!(packer.allocate(30, 30).is_none());
+
+        packer.grow(128);
+        fill(&mut packer, 30, &mut regions);
+
+        assert_eq!(packer.size(), 128);
+        assert!(regions.len() > before);
+        // Regions from before growing stay valid, so new ones must not land on them.
+        for (i, a) in regions.iter().enumerate() {
+            assert!(a.x + a.width <= 128 && a.y + a.height <= 128);
+            assert!(regions[i + 1..].iter().all(|b| !overlaps(a, b)));
+        }
+    }
+
+    #[tokio::test]
+    async fn growing_bumps_generation_and_keeps_pixels() {
+        let Some((device, queue)) = device().await else {
+            eprintln!("skipping: no software adapter");
+            return;
+        };
+        let mut atlas = Atlas::new(&device, "Test atlas", 64);
+
+        let first = atlas.allocate(&device, &queue, 40, 40).unwrap();
+        atlas.upload(&queue, first, &[255, 0, 0, 255].repeat(40 * 40));
+        assert_eq!(atlas.generation(), 0);
+
+        // Doesn't fit next to the first region, so the texture is replaced by a larger one.
+        let second = atlas.allocate(&device, &queue, 40, 40).unwrap();
+        assert_eq!(atlas.generation(), 1);
+        assert_eq!(atlas.packer.size(), 128);
+        assert!(!overlaps(&first, &second));
+
+        // Images keep their texel positions, only bind groups to the old view go stale.
+        assert_eq!(
+            read_texel(&device, &queue, &atlas, first.x + 20, first.y + 20),
+            [255, 0, 0, 255]
+        );
+    }
+}
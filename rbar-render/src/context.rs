@@ -1,18 +1,54 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
 use wgpu::{Adapter, Device, Queue};
 
+use crate::{
+    images::{Image, ImageCache},
+    structures::Size,
+    text::{TextRun, TextShaper, TextSystem},
+};
+
 #[derive(Debug, Clone)]
 pub struct WgpuContext {
     pub device: Device,
     pub queue: Queue,
     pub adapter: Adapter,
+    pub text: Arc<Mutex<TextSystem>>,
+    pub images: Arc<Mutex<ImageCache>>,
 }
 
 impl WgpuContext {
-    pub fn new(device: Device, queue: Queue, adapter: Adapter) -> Self {
+    pub fn new(device: Device, queue: Queue, adapter: Adapter, shaper: TextShaper) -> Self {
+        let text = Arc::new(Mutex::new(TextSystem::new(&device, shaper)));
+        let images = Arc::new(Mutex::new(ImageCache::new(&device)));
+
         Self {
             device,
             queue,
             adapter,
+            text,
+            images,
         }
     }
+
+    pub fn measure_text(&self, run: &TextRun, scale: f32) -> Size {
+        self.text
+            .lock()
+            .expect("text system poisoned")
+            .measure(run, scale)
+    }
+
+    pub fn load_image(&self, path: &Path, width: u32, height: u32) -> Result<Image> {
+        self.images.lock().expect("image cache poisoned").load(
+            &self.device,
+            &self.queue,
+            path,
+            width,
+            height,
+        )
+    }
 }
@@ -0,0 +1,867 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result, anyhow};
+use cosmic_text::CacheKey;
+use image::RgbaImage;
+
+use crate::{
+    atlas::{AtlasRegion, Packer},
+    images::{self, Image},
+    scene::{ClipStack, DrawCommand, Rect, Scene},
+    structures::{
+        Color, FILL_IMAGE, FILL_LINEAR_GRADIENT, FILL_RADIAL_GRADIENT, MAX_GRADIENT_STOPS, Size,
+        WidgetInstance, linear_to_srgb, srgb_to_linear,
+    },
+    text::{RasterGlyph, ShapedGlyph, TextMeasure, TextRun, TextShaper},
+};
+
+const IMAGE_ATLAS_SIZE: u32 = 1024;
+const MAX_IMAGE_ATLAS_SIZE: u32 = 8192;
+
+// Same as the quad shader: extra pixels around each widget for the anti-aliased edge.
+const AA_MARGIN: f32 = 1.0;
+
+/// Premultiplied RGBA in linear light, the values the GPU blends in.
+type Pixel = [f32; 4];
+
+/// Draws scenes on the CPU, for machines without a usable GPU adapter.
+///
+/// Every pixel goes through a port of the quad and text shaders, so output matches the wgpu
+/// renderer closely. That is plenty fast for a bar but not meant for large surfaces. Clones
+/// share fonts, glyphs and images, like surfaces created from one [`Renderer`](crate::Renderer).
+#[derive(Clone, Debug)]
+pub struct CpuRenderer {
+    resources: Arc<Mutex<Resources>>,
+}
+
+/// A pixel buffer that scenes are rasterized into, see [`CpuRenderer::create_canvas`].
+pub struct CpuCanvas {
+    resources: Arc<Mutex<Resources>>,
+    pixels: Vec<Pixel>,
+    width: u32,
+    height: u32,
+    scale: f32,
+}
+
+struct Resources {
+    shaper: TextShaper,
+    glyphs: HashMap<CacheKey, Option<RasterGlyph>>,
+    images: ImageStore,
+}
+
+/// Decoded images packed into one straight-alpha sRGB buffer, so [`Image`] handles work the
+/// same as with the GPU atlas.
+struct ImageStore {
+    packer: Packer,
+    pixels: Vec<u8>,
+    images: HashMap<(PathBuf, u32, u32), Image>,
+}
+
+/// Integer pixel bounds a draw is limited to.
+#[derive(Copy, Clone)]
+struct Bounds {
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+}
+
+impl Default for CpuRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CpuRenderer {
+    pub fn new() -> Self {
+        Self {
+            resources: Arc::new(Mutex::new(Resources {
+                shaper: TextShaper::new(),
+                glyphs: HashMap::new(),
+                images: ImageStore::new(),
+            })),
+        }
+    }
+
+    pub fn create_canvas(&self, width: u32, height: u32) -> CpuCanvas {
+        CpuCanvas {
+            resources: self.resources.clone(),
+            pixels: vec![[0.0; 4]; (width * height) as usize],
+            width,
+            height,
+            scale: 1.0,
+        }
+    }
+}
+
+impl TextMeasure for CpuRenderer {
+    fn measure_text(&self, run: &TextRun) -> Size {
+        self.resources
+            .lock()
+            .expect("cpu resources poisoned")
+            .shaper
+            .measure(run, 1.0)
+    }
+}
+
+impl fmt::Debug for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resources")
+            .field("glyphs", &self.glyphs.len())
+            .field("images", &self.images.images.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl CpuCanvas {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![[0.0; 4]; (width * height) as usize];
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Sets the ratio between canvas pixels and the logical pixels scenes are built in.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    /// Loads a PNG, JPEG or SVG file at `width` x `height` pixels.
+    ///
+    /// Images are only valid with canvases of the [`CpuRenderer`] that loaded them.
+    pub fn load_image(&self, path: &Path, width: u32, height: u32) -> Result<Image> {
+        self.resources
+            .lock()
+            .expect("cpu resources poisoned")
+            .images
+            .load(path, width, height)
+    }
+
+    /// Rasterizes `scene` into the canvas, replacing what was there.
+    pub fn render(&mut self, scene: &Scene) {
+        let area = Rect::new(
+            0.0,
+            0.0,
+            self.width as f32 / self.scale,
+            self.height as f32 / self.scale,
+        );
+        self.render_area(scene, area);
+    }
+
+    /// Rasterizes the part of `scene` inside `area`, in logical pixels, and leaves the rest of
+    /// the canvas untouched.
+    ///
+    /// Returns the canvas pixels that were redrawn, which is `area` scaled and rounded out to
+    /// whole pixels.
+    pub fn render_area(&mut self, scene: &Scene, area: Rect) -> Rect {
+        let bounds = self.bounds(area.scaled(self.scale));
+        let target = Rect::new(
+            bounds.left as f32,
+            bounds.top as f32,
+            (bounds.right - bounds.left) as f32,
+            (bounds.bottom - bounds.top) as f32,
+        );
+
+        for y in bounds.top..bounds.bottom {
+            let row = y as usize * self.width as usize;
+            self.pixels[row + bounds.left as usize..row + bounds.right as usize].fill([0.0; 4]);
+        }
+
+        let resources = self.resources.clone();
+        let mut resources = resources.lock().expect("cpu resources poisoned");
+        let Resources {
+            shaper,
+            glyphs,
+            images,
+        } = &mut *resources;
+        let mut clips = ClipStack::default();
+
+        for command in scene.commands() {
+            let clip = clips
+                .current()
+                .map_or(target, |clip| clip.intersect(&target));
+
+            match command {
+                DrawCommand::Quad(quad) => {
+                    self.draw_quad(&quad.scaled(self.scale), self.bounds(clip), images)
+                }
+                DrawCommand::Text(run) => {
+                    for glyph in shaper.glyphs(&run.scaled(self.scale)) {
+                        let raster = glyphs
+                            .entry(glyph.key)
+                            .or_insert_with(|| shaper.rasterize(glyph.key));
+
+                        if let Some(raster) = raster {
+                            self.draw_glyph(&glyph, raster, self.bounds(clip));
+                        }
+                    }
+                }
+                DrawCommand::PushClip(rect) => clips.push(rect.scaled(self.scale)),
+                DrawCommand::PopClip => clips.pop(),
+            }
+        }
+
+        target
+    }
+
+    /// Copies the canvas into `out` as premultiplied little-endian ARGB8888, the layout of
+    /// `wl_shm` buffers.
+    pub fn write_argb8888(&self, out: &mut [u8]) {
+        for (pixel, out) in self.pixels.iter().zip(out.chunks_exact_mut(4)) {
+            let [r, g, b, a] = encode(pixel);
+            let premultiply = |channel: u8| ((channel as u32 * a as u32 + 127) / 255) as u8;
+
+            out.copy_from_slice(&[premultiply(b), premultiply(g), premultiply(r), a]);
+        }
+    }
+
+    /// Returns the canvas as straight-alpha RGBA pixels.
+    pub fn to_image(&self) -> RgbaImage {
+        let pixels = self.pixels.iter().flat_map(encode).collect();
+
+        RgbaImage::from_raw(self.width, self.height, pixels)
+            .expect("canvas size matches its pixel buffer")
+    }
+
+    /// Matches how the GPU painter turns a clip rectangle into a scissor rectangle.
+    fn bounds(&self, clip: Rect) -> Bounds {
+        let width = self.width as f32;
+        let height = self.height as f32;
+        let left = clip.x.floor().max(0.0).min(width);
+        let top = clip.y.floor().max(0.0).min(height);
+
+        Bounds {
+            left: left as i32,
+            top: top as i32,
+            right: clip.right().ceil().min(width).max(left) as i32,
+            bottom: clip.bottom().ceil().min(height).max(top) as i32,
+        }
+    }
+
+    fn blend(&mut self, x: i32, y: i32, src: Pixel) {
+        if src[3] <= 0.0 {
+            return;
+        }
+
+        let dst = &mut self.pixels[y as usize * self.width as usize + x as usize];
+        *dst = over(src, *dst);
+    }
+
+    fn draw_quad(&mut self, quad: &WidgetInstance, clip: Bounds, images: &ImageStore) {
+        let margin = AA_MARGIN + decoration_extent(quad);
+        let left = (quad.position.0 - margin).floor() as i32;
+        let top = (quad.position.1 - margin).floor() as i32;
+        let right = (quad.position.0 + quad.size.0 + margin).ceil() as i32;
+        let bottom = (quad.position.1 + quad.size.1 + margin).ceil() as i32;
+        let center = (
+            quad.position.0 + quad.size.0 * 0.5,
+            quad.position.1 + quad.size.1 * 0.5,
+        );
+
+        for y in top.max(clip.top)..bottom.min(clip.bottom) {
+            for x in left.max(clip.left)..right.min(clip.right) {
+                let p = (x as f32 + 0.5 - center.0, y as f32 + 0.5 - center.1);
+                self.blend(x, y, shade(quad, p, images));
+            }
+        }
+    }
+
+    fn draw_glyph(&mut self, glyph: &ShapedGlyph, raster: &RasterGlyph, clip: Bounds) {
+        let (left, top, width, height) =
+            glyph.placement(raster.left, raster.top, raster.width, raster.height);
+
+        for row in 0..height as i32 {
+            let y = top + row;
+
+            if y < clip.top || y >= clip.bottom {
+                continue;
+            }
+
+            for column in 0..width as i32 {
+                let x = left + column;
+
+                if x < clip.left || x >= clip.right {
+                    continue;
+                }
+
+                // Rotated glyphs show the bitmap turned clockwise, like the text shader.
+                let (texel_column, texel_row) = if glyph.rotated {
+                    (row, raster.height as i32 - 1 - column)
+                } else {
+                    (column, row)
+                };
+                let offset = ((texel_row * raster.width as i32 + texel_column) * 4) as usize;
+                let texel = &raster.pixels[offset..offset + 4];
+                let alpha = texel[3] as f32 / 255.0;
+                let src = if raster.is_color {
+                    premultiply(decode(texel), 1.0)
+                } else {
+                    premultiply(glyph.color, alpha)
+                };
+
+                self.blend(x, y, src);
+            }
+        }
+    }
+}
+
+impl TextMeasure for CpuCanvas {
+    fn measure_text(&self, run: &TextRun) -> Size {
+        self.resources
+            .lock()
+            .expect("cpu resources poisoned")
+            .shaper
+            .measure(run, self.scale)
+    }
+}
+
+impl ImageStore {
+    fn new() -> Self {
+        Self {
+            packer: Packer::new(IMAGE_ATLAS_SIZE),
+            pixels: vec![0; (IMAGE_ATLAS_SIZE * IMAGE_ATLAS_SIZE * 4) as usize],
+            images: HashMap::new(),
+        }
+    }
+
+    fn load(&mut self, path: &Path, width: u32, height: u32) -> Result<Image> {
+        let key = (path.to_path_buf(), width, height);
+
+        if let Some(image) = self.images.get(&key) {
+            return Ok(*image);
+        }
+
+        let pixels = images::decode(path, width, height)
+            .with_context(|| format!("failed to load image {}", path.display()))?;
+        let region = self
+            .allocate(width, height)
+            .ok_or_else(|| anyhow!("image atlas has no room for {}", path.display()))?;
+        let size = self.packer.size() as usize;
+
+        for row in 0..height as usize {
+            let src = row * width as usize * 4;
+            let dst = ((region.y as usize + row) * size + region.x as usize) * 4;
+            self.pixels[dst..dst + width as usize * 4]
+                .copy_from_slice(&pixels[src..src + width as usize * 4]);
+        }
+
+        let image = Image { region };
+        self.images.insert(key, image);
+
+        Ok(image)
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Option<AtlasRegion> {
+        loop {
+            if let Some(region) = self.packer.allocate(width, height) {
+                return Some(region);
+            }
+
+            let size = self.packer.size();
+
+            if size >= MAX_IMAGE_ATLAS_SIZE {
+                log::warn!("CPU image atlas is full at {size}x{size}");
+                return None;
+            }
+
+            let new_size = size * 2;
+            let mut pixels = vec![0; (new_size * new_size * 4) as usize];
+
+            for (row, old) in self.pixels.chunks_exact(size as usize * 4).enumerate() {
+                let start = row * new_size as usize * 4;
+                pixels[start..start + old.len()].copy_from_slice(old);
+            }
+
+            self.pixels = pixels;
+            self.packer.grow(new_size);
+        }
+    }
+
+    /// Straight-alpha linear texel, clamped to the atlas.
+    fn texel(&self, x: i32, y: i32) -> Pixel {
+        let size = self.packer.size() as i32;
+        let offset = ((y.clamp(0, size - 1) * size + x.clamp(0, size - 1)) * 4) as usize;
+
+        let Color(r, g, b, a) = decode(&self.pixels[offset..offset + 4]);
+
+        [r, g, b, a]
+    }
+
+    /// Bilinear sample at `x`, `y` in texels, like a linear-filtering sampler.
+    fn sample(&self, x: f32, y: f32) -> Pixel {
+        let x = x - 0.5;
+        let y = y - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let top = mix(self.texel(x0, y0), self.texel(x0 + 1, y0), tx);
+        let bottom = mix(self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1), tx);
+
+        mix(top, bottom, ty)
+    }
+}
+
+/// How far outside the widget's edge the outline and an outset shadow can reach.
+fn decoration_extent(quad: &WidgetInstance) -> f32 {
+    let outline = if quad.outline_width > 0.0 {
+        (quad.outline_offset + quad.outline_width).max(0.0)
+    } else {
+        0.0
+    };
+    let shadow = if quad.shadow_color.3 > 0.0 && quad.shadow_inset == 0.0 {
+        quad.shadow_blur
+            + quad.shadow_spread.max(0.0)
+            + quad.shadow_offset.0.abs().max(quad.shadow_offset.1.abs())
+    } else {
+        0.0
+    };
+
+    outline.max(shadow)
+}
+
+/// Premultiplied color of the quad at `p`, relative to its center. Mirrors `fs_main` in
+/// `quad.wgsl`.
+fn shade(quad: &WidgetInstance, p: (f32, f32), images: &ImageStore) -> Pixel {
+    let half = (quad.size.0 * 0.5, quad.size.1 * 0.5);
+    let radius = [quad.radius.0, quad.radius.1, quad.radius.2, quad.radius.3];
+    let border_width = quad.border_width;
+    let blur = quad.shadow_blur;
+    let spread = quad.shadow_spread;
+    let shadow_p = (p.0 - quad.shadow_offset.0, p.1 - quad.shadow_offset.1);
+    let inset = quad.shadow_inset > 0.5;
+
+    let distance = sd_rounded_box(p, half, radius);
+    let outer = coverage(distance);
+    let inner = coverage(distance + border_width);
+
+    let mut color = [0.0; 4];
+
+    if !inset && quad.shadow_color.3 > 0.0 {
+        let shadow_distance = sd_rounded_box(
+            shadow_p,
+            (half.0 + spread, half.1 + spread),
+            radius.map(|r| r + spread),
+        );
+        color = premultiply(
+            quad.shadow_color,
+            blurred_coverage(shadow_distance, blur) * (1.0 - outer),
+        );
+    }
+
+    color = over(
+        fill_color(quad, p, images).map(|channel| channel * inner),
+        color,
+    );
+
+    if inset && quad.shadow_color.3 > 0.0 {
+        let inset = border_width + spread;
+        let hole_distance = sd_rounded_box(
+            shadow_p,
+            (half.0 - inset, half.1 - inset),
+            radius.map(|r| r - inset),
+        );
+        let shadow = 1.0 - blurred_coverage(hole_distance, blur);
+        color = over(premultiply(quad.shadow_color, shadow * inner), color);
+    }
+
+    if border_width > 0.0 {
+        color = over(premultiply(quad.border_color, outer - inner), color);
+    }
+
+    if quad.outline_width > 0.0 {
+        let outline_distance = distance - quad.outline_offset;
+        let ring =
+            coverage(outline_distance - quad.outline_width) * (1.0 - coverage(outline_distance));
+        color = over(premultiply(quad.outline_color, ring), color);
+    }
+
+    color
+}
+
+fn fill_color(quad: &WidgetInstance, p: (f32, f32), images: &ImageStore) -> Pixel {
+    if quad.fill_kind == FILL_IMAGE {
+        let [x, y, width, height] = quad.image_rect;
+        let u = (p.0 / quad.size.0 + 0.5).clamp(0.0, 1.0);
+        let v = (p.1 / quad.size.1 + 0.5).clamp(0.0, 1.0);
+        let texel = images.sample(
+            (x + u * width).clamp(x + 0.5, (x + width - 0.5).max(x + 0.5)),
+            (y + v * height).clamp(y + 0.5, (y + height - 0.5).max(y + 0.5)),
+        );
+        let tint = quad.color;
+
+        return premultiply(
+            Color(
+                texel[0] * tint.0,
+                texel[1] * tint.1,
+                texel[2] * tint.2,
+                texel[3] * tint.3,
+            ),
+            1.0,
+        );
+    }
+
+    if quad.fill_kind != FILL_LINEAR_GRADIENT && quad.fill_kind != FILL_RADIAL_GRADIENT {
+        return premultiply(quad.color, 1.0);
+    }
+
+    let t = gradient_position(quad, p);
+    let offsets = quad.gradient_offsets;
+    let stop_color = |i: usize| premultiply(decode(&quad.gradient_colors[i].to_le_bytes()), 1.0);
+
+    let mut color = stop_color(0);
+
+    for i in 1..MAX_GRADIENT_STOPS {
+        let span = (offsets[i] - offsets[i - 1]).max(0.0001);
+        let amount = ((t - offsets[i - 1]) / span).clamp(0.0, 1.0);
+        color = mix(color, stop_color(i), amount);
+    }
+
+    color
+}
+
+fn gradient_position(quad: &WidgetInstance, p: (f32, f32)) -> f32 {
+    let size = quad.size;
+
+    if quad.fill_kind == FILL_RADIAL_GRADIENT {
+        let center = (
+            (quad.gradient_center.0 - 0.5) * size.0,
+            (quad.gradient_center.1 - 0.5) * size.1,
+        );
+        let farthest = (center.0.abs() + size.0 * 0.5, center.1.abs() + size.1 * 0.5);
+
+        return (p.0 - center.0).hypot(p.1 - center.1) / farthest.0.hypot(farthest.1).max(0.0001);
+    }
+
+    let direction = (quad.gradient_angle.sin(), -quad.gradient_angle.cos());
+    let line_length = (size.0 * direction.0).abs() + (size.1 * direction.1).abs();
+
+    (p.0 * direction.0 + p.1 * direction.1) / line_length.max(0.0001) + 0.5
+}
+
+/// Signed distance from `p` to a box of half-extent `half` centered on the origin, with each
+/// corner rounded by its own radius (top-left, top-right, bottom-right, bottom-left).
+fn sd_rounded_box(p: (f32, f32), half: (f32, f32), radius: [f32; 4]) -> f32 {
+    let (top, bottom) = if p.0 > 0.0 {
+        (radius[1], radius[2])
+    } else {
+        (radius[0], radius[3])
+    };
+    let r = if p.1 > 0.0 { bottom } else { top }
+        .max(0.0)
+        .min(half.0.min(half.1));
+    let q = (p.0.abs() - half.0 + r, p.1.abs() - half.1 + r);
+
+    q.0.max(q.1).min(0.0) + q.0.max(0.0).hypot(q.1.max(0.0)) - r
+}
+
+fn coverage(distance: f32) -> f32 {
+    (0.5 - distance).clamp(0.0, 1.0)
+}
+
+fn blurred_coverage(distance: f32, blur: f32) -> f32 {
+    let half_blur = (blur * 0.5).max(0.5);
+    let t = ((distance + half_blur) / (2.0 * half_blur)).clamp(0.0, 1.0);
+
+    1.0 - t * t * (3.0 - 2.0 * t)
+}
+
+fn premultiply(color: Color, alpha: f32) -> Pixel {
+    let a = color.3 * alpha;
+
+    [color.0 * a, color.1 * a, color.2 * a, a]
+}
+
+fn over(src: Pixel, dst: Pixel) -> Pixel {
+    let keep = 1.0 - src[3];
+
+    [
+        src[0] + dst[0] * keep,
+        src[1] + dst[1] * keep,
+        src[2] + dst[2] * keep,
+        src[3] + dst[3] * keep,
+    ]
+}
+
+fn mix(a: Pixel, b: Pixel, amount: f32) -> Pixel {
+    [
+        a[0] + (b[0] - a[0]) * amount,
+        a[1] + (b[1] - a[1]) * amount,
+        a[2] + (b[2] - a[2]) * amount,
+        a[3] + (b[3] - a[3]) * amount,
+    ]
+}
+
+/// Turns straight-alpha sRGB bytes into a straight-alpha linear color.
+fn decode(texel: &[u8]) -> Color {
+    let channel = |value: u8| srgb_to_linear(value as f32 / 255.0);
+
+    Color(
+        channel(texel[0]),
+        channel(texel[1]),
+        channel(texel[2]),
+        texel[3] as f32 / 255.0,
+    )
+}
+
+/// Turns a premultiplied linear pixel into straight-alpha sRGB bytes, like an sRGB target does.
+fn encode(pixel: &Pixel) -> [u8; 4] {
+    let alpha = pixel[3].clamp(0.0, 1.0);
+
+    if alpha <= 0.0 {
+        return [0; 4];
+    }
+
+    let channel = |value: f32| (linear_to_srgb(value / alpha) * 255.0 + 0.5) as u8;
+
+    [
+        channel(pixel[0]),
+        channel(pixel[1]),
+        channel(pixel[2]),
+        (alpha * 255.0 + 0.5) as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::{GradientStop, Position, Radius};
+
+    const RED: Color = Color(1.0, 0.0, 0.0, 1.0);
+    const GREEN: Color = Color(0.0, 1.0, 0.0, 1.0);
+    const BLUE: Color = Color(0.0, 0.0, 1.0, 1.0);
+
+    fn assert_pixel(actual: Pixel, expected: Pixel) {
+        let close = actual
+            .iter()
+            .zip(expected)
+            .all(|(actual, expected)| (actual - expected).abs() < 0.01);
+        assert!(close, "expected {expected:?}, got {actual:?}");
+    }
+
+    /// A 100 x 10 quad filled left to right through red, green and blue.
+    fn gradient() -> WidgetInstance {
+        let stops = [(0.0, RED), (0.5, GREEN), (1.0, BLUE)]
+            .map(|(offset, color)| GradientStop { offset, color });
+
+        WidgetInstance::new(0.0, 0.0, 100.0, 10.0, RED, Radius::default())
+            .with_linear_gradient(90.0, &stops)
+    }
+
+    #[test]
+    fn gradient_interpolates_between_stops() {
+        let images = ImageStore::new();
+        let quad = gradient();
+        // Positions are relative to the center, so x = -50 is the start of the gradient line.
+        let at = |x: f32| fill_color(&quad, (x, 0.0), &images);
+
+        assert_pixel(at(-50.0), [1.0, 0.0, 0.0, 1.0]);
+        assert_pixel(at(-25.0), [0.5, 0.5, 0.0, 1.0]);
+        assert_pixel(at(0.0), [0.0, 1.0, 0.0, 1.0]);
+        assert_pixel(at(40.0), [0.0, 0.2, 0.8, 1.0]);
+        assert_pixel(at(50.0), [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn gradient_clamps_past_the_ends() {
+        let images = ImageStore::new();
+        let quad = gradient();
+
+        assert_pixel(
+            fill_color(&quad, (-80.0, 0.0), &images),
+            [1.0, 0.0, 0.0, 1.0],
+        );
+        assert_pixel(
+            fill_color(&quad, (80.0, 0.0), &images),
+            [0.0, 0.0, 1.0, 1.0],
+        );
+    }
+
+    #[test]
+    fn radial_gradient_reaches_the_farthest_corner() {
+        let images = ImageStore::new();
+        let stops = [(0.0, RED), (1.0, BLUE)].map(|(offset, color)| GradientStop { offset, color });
+        let quad = WidgetInstance::new(0.0, 0.0, 60.0, 80.0, RED, Radius::default())
+            .with_radial_gradient(Position(0.5, 0.5), &stops);
+
+        assert_pixel(fill_color(&quad, (0.0, 0.0), &images), [1.0, 0.0, 0.0, 1.0]);
+        assert_pixel(
+            fill_color(&quad, (15.0, 20.0), &images),
+            [0.5, 0.0, 0.5, 1.0],
+        );
+        assert_pixel(
+            fill_color(&quad, (30.0, -40.0), &images),
+            [0.0, 0.0, 1.0, 1.0],
+        );
+    }
+
+    /// Renders `scene` onto a 20 x 20 canvas.
+    fn render(scene: &Scene) -> RgbaImage {
+        let mut canvas = CpuRenderer::new().create_canvas(20, 20);
+        canvas.render(scene);
+        canvas.to_image()
+    }
+
+    fn pixel(image: &RgbaImage, x: u32, y: u32) -> [u8; 4] {
+        image.get_pixel(x, y).0
+    }
+
+    #[test]
+    fn rounded_box_distance() {
+        let half = (10.0, 5.0);
+        let square = [0.0; 4];
+
+        assert_eq!(sd_rounded_box((0.0, 0.0), half, square), -5.0);
+        assert_eq!(sd_rounded_box((10.0, 0.0), half, square), 0.0);
+        assert_eq!(sd_rounded_box((13.0, 0.0), half, square), 3.0);
+        assert_eq!(sd_rounded_box((13.0, 9.0), half, square), 5.0);
+
+        // Only the bottom-right corner is rounded, so the others stay sharp.
+        let rounded = [0.0, 0.0, 4.0, 0.0];
+        let corner = sd_rounded_box((10.0, 5.0), half, rounded);
+        assert!((corner - 4.0 * (2.0_f32.sqrt() - 1.0)).abs() < 1e-5);
+        assert_eq!(sd_rounded_box((-10.0, -5.0), half, rounded), 0.0);
+    }
+
+    #[test]
+    fn radius_is_limited_to_half_the_short_side() {
+        let half = (10.0, 5.0);
+
+        assert_eq!(
+            sd_rounded_box((10.0, 5.0), half, [20.0; 4]),
+            sd_rounded_box((10.0, 5.0), half, [5.0; 4])
+        );
+    }
+
+    #[test]
+    fn rounded_rect_coverage() {
+        let mut scene = Scene::new();
+        scene.rect(Rect::new(2.0, 2.0, 16.0, 16.0), RED, Radius::uniform(6.0));
+        let image = render(&scene);
+
+        assert_eq!(pixel(&image, 10, 10), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 2, 10), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 1, 10), [0; 4]);
+        // Inside the bounding box but outside the rounded corner.
+        assert_eq!(pixel(&image, 2, 2), [0; 4]);
+        assert_eq!(pixel(&image, 17, 17), [0; 4]);
+    }
+
+    #[test]
+    fn edges_between_pixels_are_anti_aliased() {
+        let mut scene = Scene::new();
+        scene.rect(Rect::new(2.5, 0.0, 10.0, 20.0), RED, Radius::default());
+        let image = render(&scene);
+
+        assert_eq!(pixel(&image, 2, 10), [255, 0, 0, 128]);
+        assert_eq!(pixel(&image, 3, 10), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 12, 10), [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn border_is_drawn_inside_the_edge() {
+        let mut scene = Scene::new();
+        scene.quad(
+            WidgetInstance::new(0.0, 0.0, 20.0, 20.0, RED, Radius::default())
+                .with_border(2.0, BLUE),
+        );
+        let image = render(&scene);
+
+        assert_eq!(pixel(&image, 0, 10), [0, 0, 255, 255]);
+        assert_eq!(pixel(&image, 1, 10), [0, 0, 255, 255]);
+        assert_eq!(pixel(&image, 10, 18), [0, 0, 255, 255]);
+        assert_eq!(pixel(&image, 2, 10), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 10, 10), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn clips_nest() {
+        let mut scene = Scene::new();
+        scene.push_clip(Rect::new(0.0, 0.0, 10.0, 20.0));
+        scene.push_clip(Rect::new(5.0, 0.0, 10.0, 10.0));
+        scene.rect(Rect::new(0.0, 0.0, 20.0, 20.0), RED, Radius::default());
+        scene.pop_clip();
+        scene.rect(Rect::new(0.0, 15.0, 20.0, 5.0), BLUE, Radius::default());
+        scene.pop_clip();
+        let image = render(&scene);
+
+        assert_eq!(pixel(&image, 7, 5), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 2, 5), [0; 4]);
+        assert_eq!(pixel(&image, 12, 5), [0; 4]);
+        assert_eq!(pixel(&image, 7, 12), [0; 4]);
+        assert_eq!(pixel(&image, 2, 17), [0, 0, 255, 255]);
+        assert_eq!(pixel(&image, 12, 17), [0; 4]);
+    }
+
+    #[test]
+    fn unbalanced_pop_leaves_the_rest_unclipped() {
+        let mut scene = Scene::new();
+        scene.pop_clip();
+        scene.rect(Rect::new(0.0, 0.0, 20.0, 20.0), RED, Radius::default());
+        let image = render(&scene);
+
+        assert_eq!(pixel(&image, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 19, 19), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn render_area_keeps_the_rest() {
+        let mut canvas = CpuRenderer::new().create_canvas(20, 20);
+        let mut scene = Scene::new();
+        scene.rect(Rect::new(0.0, 0.0, 20.0, 20.0), RED, Radius::default());
+        canvas.render(&scene);
+
+        scene.clear();
+        scene.rect(Rect::new(0.0, 0.0, 20.0, 20.0), BLUE, Radius::default());
+        let redrawn = canvas.render_area(&scene, Rect::new(0.0, 0.0, 9.5, 20.0));
+        let image = canvas.to_image();
+
+        assert_eq!(redrawn, Rect::new(0.0, 0.0, 10.0, 20.0));
+        assert_eq!(pixel(&image, 9, 10), [0, 0, 255, 255]);
+        assert_eq!(pixel(&image, 10, 10), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn scale_maps_logical_to_canvas_pixels() {
+        let mut canvas = CpuRenderer::new().create_canvas(20, 20);
+        canvas.set_scale(2.0);
+        let mut scene = Scene::new();
+        scene.rect(Rect::new(0.0, 0.0, 5.0, 5.0), RED, Radius::default());
+        canvas.render(&scene);
+        let image = canvas.to_image();
+
+        assert_eq!(pixel(&image, 9, 9), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 10, 10), [0; 4]);
+    }
+
+    #[test]
+    fn writes_premultiplied_argb() {
+        let mut canvas = CpuRenderer::new().create_canvas(1, 1);
+        let mut scene = Scene::new();
+        scene.rect(
+            Rect::new(0.0, 0.0, 1.0, 1.0),
+            Color(1.0, 0.0, 0.0, 0.5),
+            Radius::default(),
+        );
+        canvas.render(&scene);
+        let mut out = [0; 4];
+        canvas.write_argb8888(&mut out);
+
+        assert_eq!(out, [0, 0, 128, 128]);
+    }
+}
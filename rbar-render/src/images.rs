@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+use image::imageops::FilterType;
+use resvg::{tiny_skia, usvg};
+use wgpu::{Device, Queue, TextureView};
+
+use crate::atlas::{Atlas, AtlasRegion};
+
+const ATLAS_SIZE: u32 = 1024;
+
+/// A decoded image living in the shared image atlas.
+///
+/// Handles stay valid for the lifetime of the [`Renderer`](crate::Renderer) that loaded them.
+#[derive(Copy, Clone, Debug)]
+pub struct Image {
+    pub(crate) region: AtlasRegion,
+}
+
+/// Decodes PNG, JPEG and SVG files into an atlas shared by every surface.
+///
+/// Entries are keyed by path and pixel size, so an icon drawn at two sizes (or on two outputs
+/// with different scales) is rasterized once per size rather than resampled.
+pub struct ImageCache {
+    atlas: Atlas,
+    images: HashMap<(PathBuf, u32, u32), Image>,
+}
+
+impl Image {
+    pub fn width(&self) -> u32 {
+        self.region.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.region.height
+    }
+}
+
+impl fmt::Debug for ImageCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageCache")
+            .field("images", &self.images.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ImageCache {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            atlas: Atlas::new(device, "Image atlas", ATLAS_SIZE),
+            images: HashMap::new(),
+        }
+    }
+
+    pub fn atlas_view(&self) -> &TextureView {
+        self.atlas.view()
+    }
+
+    pub fn atlas_generation(&self) -> u64 {
+        self.atlas.generation()
+    }
+
+    /// Returns `path` rendered at `width` x `height` physical pixels, decoding it on first use.
+    pub fn load(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        path: &Path,
+        width: u32,
+        height: u32,
+    ) -> Result<Image> {
+        let key = (path.to_path_buf(), width, height);
+
+        if let Some(image) = self.images.get(&key) {
+            return Ok(*image);
+        }
+
+        let pixels = decode(path, width, height)
+            .with_context(|| format!("failed to load image {}", path.display()))?;
+        let region = self
+            .atlas
+            .allocate(device, queue, width, height)
+            .ok_or_else(|| anyhow!("image atlas has no room for {}", path.display()))?;
+        self.atlas.upload(queue, region, &pixels);
+
+        let image = Image { region };
+        self.images.insert(key, image);
+
+        Ok(image)
+    }
+}
+
+/// Decodes `path` into straight-alpha RGBA8 pixels of exactly `width` x `height`.
+pub(crate) fn decode(path: &Path, width: u32, height: u32) -> Result<Vec<u8>> {
+    if width == 0 || height == 0 {
+        return Err(anyhow!("image size must not be zero"));
+    }
+
+    let data = fs::read(path)?;
+    let is_svg = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"));
+
+    if is_svg {
+        return rasterize_svg(&data, width, height);
+    }
+
+    let image = image::load_from_memory(&data)?;
+    let image = if image.width() == width && image.height() == height {
+        image.into_rgba8()
+    } else {
+        image
+            .resize_exact(width, height, FilterType::Lanczos3)
+            .into_rgba8()
+    };
+
+    Ok(image.into_raw())
+}
+
+fn rasterize_svg(data: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default())?;
+    let mut pixmap =
+        tiny_skia::Pixmap::new(width, height).ok_or_else(|| anyhow!("invalid pixmap size"))?;
+    let size = tree.size();
+    let transform = tiny_skia::Transform::from_scale(
+        width as f32 / size.width(),
+        height as f32 / size.height(),
+    );
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia works in premultiplied alpha while the atlas stores straight alpha.
+    Ok(pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect())
+}
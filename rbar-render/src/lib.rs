@@ -1,38 +1,39 @@
+mod atlas;
 mod context;
+mod cpu;
+mod images;
+mod offscreen;
+mod painter;
+mod scene;
 mod structures;
+mod text;
 
-use std::ptr::NonNull;
+use std::{path::Path, ptr::NonNull};
 
-use anyhow::Result;
-use bytemuck::{bytes_of, cast_slice};
+use anyhow::{Context, Result, anyhow};
 use wayland_client::backend::ObjectId;
 use wgpu::{
-    Backends, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
-    BindGroupLayoutDescriptor, BindGroupLayoutEntry, Buffer, BufferDescriptor, BufferUsages,
-    ColorTargetState, CompositeAlphaMode, DeviceDescriptor, FragmentState, IndexFormat, Instance,
-    InstanceDescriptor, LoadOp, Operations, PipelineCompilationOptions, PipelineLayoutDescriptor,
-    PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
-    RenderPipelineDescriptor, RequestAdapterOptions, ShaderStages, StoreOp, Surface,
-    SurfaceConfiguration,
+    Backends, CompositeAlphaMode, DeviceDescriptor, Extent3d, Instance, InstanceDescriptor,
+    PresentMode, RequestAdapterOptions, Surface, SurfaceConfiguration, SurfaceError,
     SurfaceTargetUnsafe::RawHandle,
-    TextureFormat, TextureUsages, VertexState, include_wgsl,
+    SurfaceTexture, Texture, TextureDescriptor, TextureDimension, TextureUsages, TextureView,
     rwh::{RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle},
-    util::{BufferInitDescriptor, DeviceExt},
 };
 
-use crate::{
-    context::WgpuContext,
-    structures::{Color, Globals, Position, Size, WidgetInstance},
-};
+use crate::{context::WgpuContext, painter::Painter, text::TextShaper};
 
-const QUAD_VERTICES: &[Position] = &[
-    Position(-0.5, -0.5), // bottom-left
-    Position(0.5, -0.5),  // bottom-right
-    Position(-0.5, 0.5),  // top-left
-    Position(0.5, 0.5),   // top-right
-];
+pub use image::RgbaImage;
 
-const QUAD_INDICES: &[u16] = &[0, 1, 2, 2, 1, 3];
+pub use crate::{
+    cpu::{CpuCanvas, CpuRenderer},
+    images::Image,
+    offscreen::OffscreenRenderer,
+    scene::{DrawCommand, Rect, Scene},
+    structures::{
+        Color, GradientStop, MAX_GRADIENT_STOPS, Position, Radius, Shadow, Size, WidgetInstance,
+    },
+    text::{Font, TextMeasure, TextRun},
+};
 
 pub struct Renderer {
     instance: Instance,
@@ -43,93 +44,73 @@ pub struct SurfaceRenderer {
     pub surface: Surface<'static>,
     pub config: SurfaceConfiguration,
     context: WgpuContext,
-    pipeline: RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    global_buffer: wgpu::Buffer,
-    widget_buffer: wgpu::Buffer,
-    widget_count: u32,
-    bind_group: BindGroup,
-    num_indices: u32,
+    painter: Painter,
+    /// The last frame, so damaged areas can be redrawn on their own. `None` when frames can't
+    /// be copied into the surface, which then redraws everything every frame.
+    canvas: Option<Canvas>,
+}
+
+/// A texture keeping the last frame.
+struct Canvas {
+    texture: Texture,
+    view: TextureView,
 }
 
 impl Renderer {
     pub async fn new() -> Result<Self> {
+        Self::with_adapter(false, TextShaper::new()).await
+    }
+
+    /// Creates a renderer on the fallback (CPU) adapter, such as lavapipe or llvmpipe.
+    ///
+    /// Meant for headless rendering where output must not depend on the host's GPU.
+    pub async fn new_software() -> Result<Self> {
+        Self::with_adapter(true, TextShaper::new()).await
+    }
+
+    /// Like [`new_software`](Self::new_software), but text only uses the font files in `fonts`,
+    /// so output doesn't depend on the host's fonts either. The first font stands in for the
+    /// generic families.
+    pub async fn new_software_with_fonts(fonts: &[&Path]) -> Result<Self> {
+        Self::with_adapter(true, TextShaper::with_fonts(fonts)?).await
+    }
+
+    async fn with_adapter(force_fallback_adapter: bool, shaper: TextShaper) -> Result<Self> {
         let instance = Instance::new(&InstanceDescriptor {
             backends: Backends::all(),
             ..Default::default()
         });
 
         let adapter = instance
-            .request_adapter(&RequestAdapterOptions::default())
+            .request_adapter(&RequestAdapterOptions {
+                force_fallback_adapter,
+                ..Default::default()
+            })
             .await?;
         let (device, queue) = adapter.request_device(&DeviceDescriptor::default()).await?;
-        let context = WgpuContext::new(device, queue, adapter);
+        let context = WgpuContext::new(device, queue, adapter, shaper);
 
         Ok(Self { instance, context })
     }
 
-    fn create_pipeline(
-        &self,
-        format: TextureFormat,
-        bind_group_layout: &BindGroupLayout,
-    ) -> RenderPipeline {
-        let shader = self
-            .context
-            .device
-            .create_shader_module(include_wgsl!("../shaders/quad.wgsl"));
-
-        let layout = self
-            .context
-            .device
-            .create_pipeline_layout(&PipelineLayoutDescriptor {
-                label: Some("pipeline layout"),
-                bind_group_layouts: &[bind_group_layout],
-                push_constant_ranges: &[],
-            });
-
-        self.context
-            .device
-            .create_render_pipeline(&RenderPipelineDescriptor {
-                label: Some("pipeline"),
-                layout: Some(&layout),
-                vertex: VertexState {
-                    module: &shader,
-                    entry_point: Some("vs_main"),
-                    buffers: &[Position::descriptor(), WidgetInstance::descriptor()],
-                    compilation_options: PipelineCompilationOptions::default(),
-                },
-                fragment: Some(FragmentState {
-                    module: &shader,
-                    entry_point: Some("fs_main"),
-                    targets: &[Some(ColorTargetState {
-                        format,
-                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                    compilation_options: PipelineCompilationOptions::default(),
-                }),
-                primitive: PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-                cache: None,
-            })
+    /// Creates a renderer that draws into a texture instead of a Wayland surface.
+    pub fn create_offscreen_renderer(&self, width: u32, height: u32) -> OffscreenRenderer {
+        OffscreenRenderer::new(&self.context, width, height)
     }
 
-    pub fn create_surface_renderer<'a>(
-        &'a self,
+    pub fn create_surface_renderer(
+        &self,
         backend: &wayland_client::backend::Backend,
         surface_id: ObjectId,
         width: u32,
         height: u32,
-    ) -> SurfaceRenderer {
-        let raw_display_handle = RawDisplayHandle::Wayland(WaylandDisplayHandle::new(
-            NonNull::new(backend.display_ptr() as *mut _).unwrap(),
-        ));
-        let raw_window_handle = RawWindowHandle::Wayland(WaylandWindowHandle::new(
-            NonNull::new(surface_id.as_ptr() as *mut _).unwrap(),
-        ));
+    ) -> Result<SurfaceRenderer> {
+        let display = NonNull::new(backend.display_ptr() as *mut _)
+            .ok_or_else(|| anyhow!("the Wayland connection has no display"))?;
+        let window = NonNull::new(surface_id.as_ptr() as *mut _)
+            .ok_or_else(|| anyhow!("the Wayland surface is dead"))?;
+        let raw_display_handle = RawDisplayHandle::Wayland(WaylandDisplayHandle::new(display));
+        let raw_window_handle = RawWindowHandle::Wayland(WaylandWindowHandle::new(window));
 
         let surface = unsafe {
             self.instance
@@ -137,144 +118,127 @@ impl Renderer {
                     raw_display_handle,
                     raw_window_handle,
                 })
-                .unwrap()
+                .context("failed to create a wgpu surface")?
         };
         let surface_caps = surface.get_capabilities(&self.context.adapter);
         let surface_format = surface_caps
             .formats
             .iter()
             .find(|f| f.is_srgb())
+            .or(surface_caps.formats.first())
             .copied()
-            .unwrap_or(surface_caps.formats[0]);
+            .ok_or_else(|| anyhow!("the adapter can't present to the surface"))?;
         let alpha_mode = surface_caps
             .alpha_modes
             .iter()
             .find(|&m| *m == CompositeAlphaMode::PreMultiplied)
             .copied()
             .unwrap_or(CompositeAlphaMode::Auto);
+        let copyable = surface_caps.usages.contains(TextureUsages::COPY_DST);
+        let usage = if copyable {
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST
+        } else {
+            TextureUsages::RENDER_ATTACHMENT
+        };
         let config = SurfaceConfiguration {
-            usage: TextureUsages::RENDER_ATTACHMENT,
+            usage,
             format: surface_format,
             width,
             height,
             alpha_mode,
-            present_mode: surface_caps.present_modes[0],
+            // Every surface supports FIFO, even when the adapter doesn't list it.
+            present_mode: surface_caps
+                .present_modes
+                .first()
+                .copied()
+                .unwrap_or(PresentMode::Fifo),
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
         surface.configure(&self.context.device, &config);
-        let bind_group_layout =
-            self.context
-                .device
-                .create_bind_group_layout(&BindGroupLayoutDescriptor {
-                    label: Some("Bind Group"),
-                    entries: &[BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::VERTEX_FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }],
-                });
-        let global_buffer = self.context.device.create_buffer(&BufferDescriptor {
-            label: Some("Global buffer"),
-            size: size_of::<Globals>() as u64,
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-        let bind_group = self.context.device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Globals bind group"),
-            layout: &bind_group_layout,
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: global_buffer.as_entire_binding(),
-            }],
-        });
-        let pipeline = self.create_pipeline(surface_format, &bind_group_layout);
-        let vertex_buffer = self
-            .context
-            .device
-            .create_buffer_init(&BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: cast_slice(QUAD_VERTICES),
-                usage: BufferUsages::VERTEX,
-            });
-        let index_buffer = self
-            .context
-            .device
-            .create_buffer_init(&BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: cast_slice(QUAD_INDICES),
-                usage: BufferUsages::INDEX,
-            });
-        let widget_buffer = self
-            .context
-            .device
-            .create_buffer_init(&BufferInitDescriptor {
-                label: Some("Widget Instance Buffer"),
-                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-                contents: cast_slice(&[WidgetInstance::new(
-                    0.0,
-                    0.0,
-                    100.0,
-                    30.0,
-                    Color(1.0, 0.0, 0.0, 1.0),
-                    0.0,
-                )]),
-            });
-
-        SurfaceRenderer {
+        let mut painter = Painter::new(&self.context, surface_format);
+        painter.set_size(width, height);
+
+        Ok(SurfaceRenderer {
+            canvas: copyable.then(|| Canvas::new(&self.context, &config)),
             surface,
             config,
-            pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_indices: QUAD_INDICES.len() as u32,
             context: self.context.clone(),
-            global_buffer,
-            widget_buffer,
-            widget_count: 1,
-            bind_group,
-        }
+            painter,
+        })
+    }
+}
+
+impl TextMeasure for SurfaceRenderer {
+    fn measure_text(&self, run: &TextRun) -> Size {
+        self.context.measure_text(run, self.painter.scale())
     }
 }
 
 impl SurfaceRenderer {
-    pub fn render(&mut self) -> Result<()> {
-        let frame = self.surface.get_current_texture()?;
-        let view = frame.texture.create_view(&Default::default());
+    /// Loads a PNG, JPEG or SVG file at `width` x `height` pixels into the shared image atlas.
+    ///
+    /// Draw it with [`Scene::image`] or [`WidgetInstance::with_image`]. Load icons at their
+    /// logical size times [`scale`](Self::scale) so they stay sharp on HiDPI outputs.
+    pub fn load_image(&self, path: &Path, width: u32, height: u32) -> Result<Image> {
+        self.context.load_image(path, width, height)
+    }
 
+    /// Draws `scene` and presents it.
+    ///
+    /// A surface that was lost or no longer matches the window is configured again and the
+    /// frame retried once.
+    pub fn render(&mut self, scene: &Scene) -> Result<()> {
+        let scale = self.painter.scale();
+        let area = Rect::new(
+            0.0,
+            0.0,
+            self.config.width as f32 / scale,
+            self.config.height as f32 / scale,
+        );
+
+        self.render_damaged(scene, &[area])
+    }
+
+    /// The buffer pixels [`render_damaged`](Self::render_damaged) changes for `damage`, for
+    /// reporting to the compositor with `wl_surface.damage_buffer` before the frame is
+    /// presented.
+    pub fn damaged_pixels(&self, damage: &[Rect]) -> Vec<Rect> {
+        damage
+            .iter()
+            .filter_map(|area| self.painter.bounds(*area))
+            .collect()
+    }
+
+    /// Draws the parts of `scene` inside the `damage` areas, in scene units, over the last
+    /// frame and presents the result.
+    ///
+    /// Everything is redrawn when the surface can't be copied into, which leaves the pixels
+    /// outside `damage` unchanged anyway.
+    pub fn render_damaged(&mut self, scene: &Scene, damage: &[Rect]) -> Result<()> {
+        self.painter.prepare(scene);
+        let frame = self.current_frame()?;
         let mut encoder = self
             .context
             .device
             .create_command_encoder(&Default::default());
 
-        {
-            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: StoreOp::Store,
-                    },
-                    depth_slice: None,
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-
-            pass.set_pipeline(&self.pipeline);
-            pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            pass.set_vertex_buffer(1, self.widget_buffer.slice(..));
-            pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
-            pass.set_bind_group(0, &self.bind_group, &[]);
-            pass.draw_indexed(0..self.num_indices, 0, 0..self.widget_count);
+        match &self.canvas {
+            Some(canvas) => {
+                for bounds in self.damaged_pixels(damage) {
+                    self.painter.encode_area(&mut encoder, &canvas.view, bounds);
+                }
+
+                encoder.copy_texture_to_texture(
+                    canvas.texture.as_image_copy(),
+                    frame.texture.as_image_copy(),
+                    frame.texture.size(),
+                );
+            }
+            None => {
+                let view = frame.texture.create_view(&Default::default());
+                self.painter.encode(&mut encoder, &view);
+            }
         }
 
         self.context.queue.submit([encoder.finish()]);
@@ -283,15 +247,66 @@ impl SurfaceRenderer {
         Ok(())
     }
 
+    fn current_frame(&mut self) -> Result<SurfaceTexture> {
+        match self.surface.get_current_texture() {
+            Ok(frame) => Ok(frame),
+            Err(SurfaceError::Lost | SurfaceError::Outdated) => {
+                self.surface.configure(&self.context.device, &self.config);
+                self.surface
+                    .get_current_texture()
+                    .context("failed to get a frame after reconfiguring the surface")
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.painter.scale()
+    }
+
+    /// Sets the ratio between buffer pixels and the logical pixels scenes are built in.
+    ///
+    /// Sizes passed to [`set_size`](Self::set_size) are buffer pixels, so a 30 pixel tall bar
+    /// at 1.5x needs a 45 pixel tall surface.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.painter.set_scale(scale);
+    }
+
+    /// Resizes the surface, after which the next frame has to redraw everything.
     pub fn set_size(&mut self, width: u32, height: u32) {
         self.config.width = width;
         self.config.height = height;
         self.surface.configure(&self.context.device, &self.config);
-        let new_globals = Globals {
-            resolution: Size(width as f32, height as f32),
-        };
-        self.context
-            .queue
-            .write_buffer(&self.global_buffer, 0, bytes_of(&new_globals));
+        self.painter.set_size(width, height);
+
+        if self.canvas.is_some() {
+            self.canvas = Some(Canvas::new(&self.context, &self.config));
+        }
+    }
+}
+
+impl Canvas {
+    fn new(context: &WgpuContext, config: &SurfaceConfiguration) -> Self {
+        let texture = context.device.create_texture(&TextureDescriptor {
+            label: Some("Surface canvas"),
+            size: Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: config.format,
+            usage: TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::COPY_SRC
+                | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        Self {
+            view: texture.create_view(&Default::default()),
+            texture,
+        }
     }
 }
@@ -0,0 +1,175 @@
+use std::{path::Path, sync::mpsc};
+
+use anyhow::{Context, Result, anyhow};
+use image::RgbaImage;
+use wgpu::{
+    BufferDescriptor, BufferUsages, COPY_BYTES_PER_ROW_ALIGNMENT, Extent3d, MapMode, PollType,
+    TexelCopyBufferInfo, TexelCopyBufferLayout, Texture, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages,
+};
+
+use crate::{
+    context::WgpuContext,
+    images::Image,
+    painter::Painter,
+    scene::Scene,
+    structures::Size,
+    text::{TextMeasure, TextRun},
+};
+
+/// Same format the surface renderer prefers, so screenshots match what is shown on screen.
+const FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+/// Renders scenes into a texture and reads them back, without any Wayland surface.
+///
+/// Useful for screenshots, documentation images and pixel-diff golden tests; pair it with
+/// [`Renderer::new_software`](crate::Renderer::new_software) for results that do not depend on
+/// the host GPU.
+pub struct OffscreenRenderer {
+    context: WgpuContext,
+    painter: Painter,
+    texture: Texture,
+    width: u32,
+    height: u32,
+}
+
+impl OffscreenRenderer {
+    pub(crate) fn new(context: &WgpuContext, width: u32, height: u32) -> Self {
+        let mut painter = Painter::new(context, FORMAT);
+        painter.set_size(width, height);
+
+        Self {
+            context: context.clone(),
+            painter,
+            texture: create_texture(context, width, height),
+            width,
+            height,
+        }
+    }
+
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.texture = create_texture(&self.context, width, height);
+        self.painter.set_size(width, height);
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.painter.scale()
+    }
+
+    /// Sets the ratio between target pixels and the logical pixels scenes are built in.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.painter.set_scale(scale);
+    }
+
+    pub fn load_image(&self, path: &Path, width: u32, height: u32) -> Result<Image> {
+        self.context.load_image(path, width, height)
+    }
+
+    /// Draws `scene` and returns the result as straight-alpha RGBA pixels.
+    pub fn render(&mut self, scene: &Scene) -> Result<RgbaImage> {
+        self.painter.prepare(scene);
+        let view = self.texture.create_view(&Default::default());
+        let padded_row =
+            (self.width * 4).div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+        let readback = self.context.device.create_buffer(&BufferDescriptor {
+            label: Some("Offscreen readback buffer"),
+            size: padded_row as u64 * self.height as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .context
+            .device
+            .create_command_encoder(&Default::default());
+        self.painter.encode(&mut encoder, &view);
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            TexelCopyBufferInfo {
+                buffer: &readback,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.context.queue.submit([encoder.finish()]);
+
+        let slice = readback.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.context
+            .device
+            .poll(PollType::wait_indefinitely())
+            .context("failed to wait for the offscreen frame")?;
+        receiver
+            .recv()
+            .context("readback buffer was dropped before mapping")??;
+
+        let mapped = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((self.width * self.height * 4) as usize);
+
+        for row in mapped.chunks_exact(padded_row as usize) {
+            pixels.extend_from_slice(&row[..(self.width * 4) as usize]);
+        }
+
+        drop(mapped);
+        readback.unmap();
+
+        // Blending onto a transparent clear leaves premultiplied colors behind, PNG wants them
+        // straight.
+        for pixel in pixels.chunks_exact_mut(4) {
+            let alpha = pixel[3];
+
+            if alpha != 0 && alpha != 255 {
+                for channel in &mut pixel[..3] {
+                    *channel =
+                        ((*channel as u32 * 255 + alpha as u32 / 2) / alpha as u32).min(255) as u8;
+                }
+            }
+        }
+
+        RgbaImage::from_raw(self.width, self.height, pixels)
+            .ok_or_else(|| anyhow!("readback size does not match the target"))
+    }
+
+    /// Draws `scene` and writes it to `path` as a PNG.
+    pub fn render_to_png(&mut self, scene: &Scene, path: &Path) -> Result<()> {
+        self.render(scene)?
+            .save(path)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+impl TextMeasure for OffscreenRenderer {
+    fn measure_text(&self, run: &TextRun) -> Size {
+        self.context.measure_text(run, self.painter.scale())
+    }
+}
+
+fn create_texture(context: &WgpuContext, width: u32, height: u32) -> Texture {
+    context.device.create_texture(&TextureDescriptor {
+        label: Some("Offscreen target"),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: FORMAT,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
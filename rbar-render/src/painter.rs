@@ -0,0 +1,552 @@
+use std::{marker::PhantomData, ops::Range};
+
+use bytemuck::{Pod, bytes_of, cast_slice};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferBindingType,
+    BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, FilterMode,
+    FragmentState, IndexFormat, LoadOp, MultisampleState, Operations, PipelineCompilationOptions,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPass, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderModuleDescriptor, ShaderStages, StoreOp, TextureFormat,
+    TextureSampleType, TextureView, TextureViewDimension, VertexBufferLayout, VertexState,
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+};
+
+use crate::{
+    context::WgpuContext,
+    scene::{ClipStack, DrawCommand, Rect, Scene},
+    structures::{Globals, GlyphInstance, Position, Size, WidgetInstance},
+};
+
+const QUAD_VERTICES: &[Position] = &[
+    Position(-0.5, -0.5), // bottom-left
+    Position(0.5, -0.5),  // bottom-right
+    Position(-0.5, 0.5),  // top-left
+    Position(0.5, 0.5),   // top-right
+];
+
+const QUAD_INDICES: &[u16] = &[0, 1, 2, 2, 1, 3];
+
+const INITIAL_INSTANCE_CAPACITY: usize = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum BatchKind {
+    Quads,
+    Glyphs,
+}
+
+/// A run of consecutive instances of one kind sharing a clip region.
+#[derive(Clone, Debug)]
+struct Batch {
+    kind: BatchKind,
+    instances: Range<u32>,
+    clip: Option<Rect>,
+}
+
+/// A vertex buffer that is reallocated at the next power of two when it runs out of room.
+struct InstanceBuffer<T> {
+    label: &'static str,
+    buffer: Buffer,
+    capacity: usize,
+    _instance: PhantomData<T>,
+}
+
+/// Draws a [`Scene`] into whatever render pass it is given.
+///
+/// The pipelines, atlas bind groups and instance buffers live here so that surfaces and
+/// offscreen targets share the exact same drawing code.
+pub struct Painter {
+    context: WgpuContext,
+    quad_pipeline: RenderPipeline,
+    text_pipeline: RenderPipeline,
+    clear_pipeline: RenderPipeline,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    num_indices: u32,
+    global_buffer: Buffer,
+    globals_bind_group: BindGroup,
+    atlas_bind_group_layout: BindGroupLayout,
+    image_bind_group: BindGroup,
+    image_atlas_generation: u64,
+    image_sampler: Sampler,
+    text_bind_group: BindGroup,
+    glyph_atlas_generation: u64,
+    glyph_sampler: Sampler,
+    widget_buffer: InstanceBuffer<WidgetInstance>,
+    glyph_buffer: InstanceBuffer<GlyphInstance>,
+    widgets: Vec<WidgetInstance>,
+    glyphs: Vec<GlyphInstance>,
+    batches: Vec<Batch>,
+    width: u32,
+    height: u32,
+    scale: f32,
+}
+
+impl<T: Pod> InstanceBuffer<T> {
+    fn new(context: &WgpuContext, label: &'static str) -> Self {
+        Self {
+            label,
+            buffer: Self::create(context, label, INITIAL_INSTANCE_CAPACITY),
+            capacity: INITIAL_INSTANCE_CAPACITY,
+            _instance: PhantomData,
+        }
+    }
+
+    fn create(context: &WgpuContext, label: &'static str, capacity: usize) -> Buffer {
+        context.device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: (capacity * size_of::<T>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn upload(&mut self, context: &WgpuContext, instances: &[T]) {
+        if instances.len() > self.capacity {
+            self.capacity = instances.len().next_power_of_two();
+            self.buffer = Self::create(context, self.label, self.capacity);
+        }
+
+        context
+            .queue
+            .write_buffer(&self.buffer, 0, cast_slice(instances));
+    }
+}
+
+impl Painter {
+    pub fn new(context: &WgpuContext, format: TextureFormat) -> Self {
+        let device = &context.device;
+        let globals_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Bind Group"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let global_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Global buffer"),
+            size: size_of::<Globals>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let globals_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Globals bind group"),
+            layout: &globals_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: global_buffer.as_entire_binding(),
+            }],
+        });
+        let atlas_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Atlas bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let quad_pipeline = create_pipeline(
+            context,
+            "Quad pipeline",
+            include_wgsl!("../shaders/quad.wgsl"),
+            format,
+            &[&globals_bind_group_layout, &atlas_bind_group_layout],
+            &[Position::descriptor(), WidgetInstance::descriptor()],
+            Some(BlendState::ALPHA_BLENDING),
+        );
+        let text_pipeline = create_pipeline(
+            context,
+            "Text pipeline",
+            include_wgsl!("../shaders/text.wgsl"),
+            format,
+            &[&globals_bind_group_layout, &atlas_bind_group_layout],
+            &[Position::descriptor(), GlyphInstance::descriptor()],
+            Some(BlendState::ALPHA_BLENDING),
+        );
+        // Without blending the transparent output replaces what is there.
+        let clear_pipeline = create_pipeline(
+            context,
+            "Clear pipeline",
+            include_wgsl!("../shaders/clear.wgsl"),
+            format,
+            &[],
+            &[],
+            None,
+        );
+        // Glyphs are rasterized at their final pixel position, images may be scaled.
+        let glyph_sampler = device.create_sampler(&Default::default());
+        let image_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Image sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let (text_bind_group, glyph_atlas_generation) = {
+            let text = context.text.lock().expect("text system poisoned");
+            (
+                create_atlas_bind_group(
+                    context,
+                    &atlas_bind_group_layout,
+                    text.atlas_view(),
+                    &glyph_sampler,
+                ),
+                text.atlas_generation(),
+            )
+        };
+        let (image_bind_group, image_atlas_generation) = {
+            let images = context.images.lock().expect("image cache poisoned");
+            (
+                create_atlas_bind_group(
+                    context,
+                    &atlas_bind_group_layout,
+                    images.atlas_view(),
+                    &image_sampler,
+                ),
+                images.atlas_generation(),
+            )
+        };
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: cast_slice(QUAD_VERTICES),
+            usage: BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: cast_slice(QUAD_INDICES),
+            usage: BufferUsages::INDEX,
+        });
+
+        Self {
+            context: context.clone(),
+            quad_pipeline,
+            text_pipeline,
+            clear_pipeline,
+            vertex_buffer,
+            index_buffer,
+            num_indices: QUAD_INDICES.len() as u32,
+            global_buffer,
+            globals_bind_group,
+            atlas_bind_group_layout,
+            image_bind_group,
+            image_atlas_generation,
+            image_sampler,
+            text_bind_group,
+            glyph_atlas_generation,
+            glyph_sampler,
+            widget_buffer: InstanceBuffer::new(context, "Widget Instance Buffer"),
+            glyph_buffer: InstanceBuffer::new(context, "Glyph Instance Buffer"),
+            widgets: vec![],
+            glyphs: vec![],
+            batches: vec![],
+            width: 0,
+            height: 0,
+            scale: 1.0,
+        }
+    }
+
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        let new_globals = Globals {
+            resolution: Size(width as f32, height as f32),
+        };
+        self.context
+            .queue
+            .write_buffer(&self.global_buffer, 0, bytes_of(&new_globals));
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Sets how many target pixels one scene unit covers.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    /// Turns `scene` into instance batches and uploads them, rasterizing any new glyphs.
+    pub fn prepare(&mut self, scene: &Scene) {
+        self.widgets.clear();
+        self.glyphs.clear();
+        self.batches.clear();
+        let mut clips = ClipStack::default();
+        let mut text = self.context.text.lock().expect("text system poisoned");
+
+        for command in scene.commands() {
+            match command {
+                DrawCommand::Quad(quad) => {
+                    let start = self.widgets.len() as u32;
+                    self.widgets.push(quad.scaled(self.scale));
+                    push_batch(
+                        &mut self.batches,
+                        BatchKind::Quads,
+                        start..start + 1,
+                        clips.current(),
+                    );
+                }
+                DrawCommand::Text(run) => {
+                    let start = self.glyphs.len() as u32;
+                    text.prepare(
+                        &self.context.device,
+                        &self.context.queue,
+                        &run.scaled(self.scale),
+                        &mut self.glyphs,
+                    );
+                    let end = self.glyphs.len() as u32;
+                    push_batch(
+                        &mut self.batches,
+                        BatchKind::Glyphs,
+                        start..end,
+                        clips.current(),
+                    );
+                }
+                DrawCommand::PushClip(rect) => clips.push(rect.scaled(self.scale)),
+                DrawCommand::PopClip => clips.pop(),
+            }
+        }
+
+        if text.atlas_generation() != self.glyph_atlas_generation {
+            self.text_bind_group = create_atlas_bind_group(
+                &self.context,
+                &self.atlas_bind_group_layout,
+                text.atlas_view(),
+                &self.glyph_sampler,
+            );
+            self.glyph_atlas_generation = text.atlas_generation();
+        }
+        drop(text);
+
+        let images = self.context.images.lock().expect("image cache poisoned");
+
+        if images.atlas_generation() != self.image_atlas_generation {
+            self.image_bind_group = create_atlas_bind_group(
+                &self.context,
+                &self.atlas_bind_group_layout,
+                images.atlas_view(),
+                &self.image_sampler,
+            );
+            self.image_atlas_generation = images.atlas_generation();
+        }
+        drop(images);
+
+        self.widget_buffer.upload(&self.context, &self.widgets);
+        self.glyph_buffer.upload(&self.context, &self.glyphs);
+    }
+
+    /// Clears `view` and records the batches from the last [`prepare`](Self::prepare) into it.
+    pub fn encode(&self, encoder: &mut CommandEncoder, view: &TextureView) {
+        let mut pass = begin_pass(encoder, view, LoadOp::Clear(wgpu::Color::TRANSPARENT));
+        self.draw(&mut pass, None);
+    }
+
+    /// Clears the part of `view` inside `bounds`, from [`bounds`](Self::bounds), and records
+    /// the batches from the last [`prepare`](Self::prepare) into it. The rest of `view` is
+    /// left as it was.
+    pub fn encode_area(&self, encoder: &mut CommandEncoder, view: &TextureView, bounds: Rect) {
+        let mut pass = begin_pass(encoder, view, LoadOp::Load);
+        pass.set_scissor_rect(
+            bounds.x as u32,
+            bounds.y as u32,
+            bounds.width as u32,
+            bounds.height as u32,
+        );
+        pass.set_pipeline(&self.clear_pipeline);
+        pass.draw(0..3, 0..1);
+        self.draw(&mut pass, Some(bounds));
+    }
+
+    /// The target pixels `area`, in scene units, covers, rounded out to whole pixels, or `None`
+    /// if it is outside the target.
+    pub fn bounds(&self, area: Rect) -> Option<Rect> {
+        let (x, y, width, height) = self.scissor(Some(area.scaled(self.scale)))?;
+
+        Some(Rect::new(x as f32, y as f32, width as f32, height as f32))
+    }
+
+    /// Draws the batches, only inside `within` if given.
+    fn draw(&self, pass: &mut RenderPass, within: Option<Rect>) {
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
+        pass.set_bind_group(0, &self.globals_bind_group, &[]);
+
+        for batch in &self.batches {
+            let clip = match (batch.clip, within) {
+                (Some(clip), Some(within)) => Some(clip.intersect(&within)),
+                (clip, within) => clip.or(within),
+            };
+            let Some((x, y, width, height)) = self.scissor(clip) else {
+                continue;
+            };
+            pass.set_scissor_rect(x, y, width, height);
+
+            match batch.kind {
+                BatchKind::Quads => {
+                    pass.set_pipeline(&self.quad_pipeline);
+                    pass.set_vertex_buffer(1, self.widget_buffer.buffer.slice(..));
+                    pass.set_bind_group(1, &self.image_bind_group, &[]);
+                }
+                BatchKind::Glyphs => {
+                    pass.set_pipeline(&self.text_pipeline);
+                    pass.set_vertex_buffer(1, self.glyph_buffer.buffer.slice(..));
+                    pass.set_bind_group(1, &self.text_bind_group, &[]);
+                }
+            }
+
+            pass.draw_indexed(0..self.num_indices, 0, batch.instances.clone());
+        }
+    }
+
+    /// Converts a clip into a scissor rect inside the target, or `None` if nothing is visible.
+    fn scissor(&self, clip: Option<Rect>) -> Option<(u32, u32, u32, u32)> {
+        let target = Rect::new(0.0, 0.0, self.width as f32, self.height as f32);
+        let clip = clip.map_or(target, |clip| clip.intersect(&target));
+        let x = clip.x.floor();
+        let y = clip.y.floor();
+        let width = (clip.right().ceil() - x) as u32;
+        let height = (clip.bottom().ceil() - y) as u32;
+
+        (width > 0 && height > 0).then_some((x as u32, y as u32, width, height))
+    }
+}
+
+fn begin_pass<'a>(
+    encoder: &'a mut CommandEncoder,
+    view: &TextureView,
+    load: LoadOp<wgpu::Color>,
+) -> RenderPass<'a> {
+    encoder.begin_render_pass(&RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: Operations {
+                load,
+                store: StoreOp::Store,
+            },
+            depth_slice: None,
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
+/// Extends the last batch when it has the same kind and clip, so z-order is kept without
+/// splitting every command into its own draw call.
+fn push_batch(
+    batches: &mut Vec<Batch>,
+    kind: BatchKind,
+    instances: Range<u32>,
+    clip: Option<Rect>,
+) {
+    if instances.is_empty() {
+        return;
+    }
+
+    if let Some(last) = batches.last_mut()
+        && last.kind == kind
+        && last.clip == clip
+        && last.instances.end == instances.start
+    {
+        last.instances.end = instances.end;
+        return;
+    }
+
+    batches.push(Batch {
+        kind,
+        instances,
+        clip,
+    });
+}
+
+fn create_pipeline(
+    context: &WgpuContext,
+    label: &str,
+    shader: ShaderModuleDescriptor,
+    format: TextureFormat,
+    bind_group_layouts: &[&BindGroupLayout],
+    buffers: &[VertexBufferLayout],
+    blend: Option<BlendState>,
+) -> RenderPipeline {
+    let shader = context.device.create_shader_module(shader);
+
+    let layout = context
+        .device
+        .create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+    context
+        .device
+        .create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers,
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+}
+
+fn create_atlas_bind_group(
+    context: &WgpuContext,
+    layout: &BindGroupLayout,
+    view: &TextureView,
+    sampler: &Sampler,
+) -> BindGroup {
+    context.device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Atlas bind group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
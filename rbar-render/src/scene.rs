@@ -0,0 +1,252 @@
+use crate::{
+    images::Image,
+    structures::{Color, Radius, WidgetInstance},
+    text::TextRun,
+};
+
+/// An axis-aligned rectangle in surface pixels, positioned by its top-left corner.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Clone, Debug)]
+pub enum DrawCommand {
+    Quad(WidgetInstance),
+    Text(TextRun),
+    PushClip(Rect),
+    PopClip,
+}
+
+/// Everything a surface should show in one frame, in back-to-front order.
+///
+/// Build a scene each frame and hand it to [`SurfaceRenderer::render`](crate::SurfaceRenderer).
+/// Later commands are drawn on top of earlier ones, and clip regions nest: a pushed clip is
+/// intersected with the one already active until the matching [`pop_clip`](Scene::pop_clip).
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    commands: Vec<DrawCommand>,
+}
+
+/// The clip region in effect while walking a scene's commands, shared by the renderers so they
+/// agree on how clips nest.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ClipStack {
+    clips: Vec<Rect>,
+}
+
+impl Rect {
+    pub const fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> f32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.y + self.height
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+
+        Rect {
+            x,
+            y,
+            width: (self.right().min(other.right()) - x).max(0.0),
+            height: (self.bottom().min(other.bottom()) - y).max(0.0),
+        }
+    }
+
+    pub fn scaled(&self, scale: f32) -> Rect {
+        Rect::new(
+            self.x * scale,
+            self.y * scale,
+            self.width * scale,
+            self.height * scale,
+        )
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn commands(&self) -> &[DrawCommand] {
+        &self.commands
+    }
+
+    /// Draws a quad with any fill, border, outline or shadow it was built with.
+    pub fn quad(&mut self, quad: WidgetInstance) {
+        self.commands.push(DrawCommand::Quad(quad));
+    }
+
+    /// Draws a solid rectangle.
+    pub fn rect(&mut self, rect: Rect, color: Color, radius: Radius) {
+        self.quad(WidgetInstance::new(
+            rect.x,
+            rect.y,
+            rect.width,
+            rect.height,
+            color,
+            radius,
+        ));
+    }
+
+    pub fn text(&mut self, run: TextRun) {
+        self.commands.push(DrawCommand::Text(run));
+    }
+
+    /// Draws `image` stretched over `rect`.
+    pub fn image(&mut self, rect: Rect, image: &Image) {
+        self.quad(
+            WidgetInstance::new(
+                rect.x,
+                rect.y,
+                rect.width,
+                rect.height,
+                Color(1.0, 1.0, 1.0, 1.0),
+                Radius::default(),
+            )
+            .with_image(image),
+        );
+    }
+
+    pub fn push_clip(&mut self, rect: Rect) {
+        self.commands.push(DrawCommand::PushClip(rect));
+    }
+
+    /// Restores the clip from before the matching [`push_clip`](Scene::push_clip). Popping
+    /// more clips than were pushed is ignored, leaving the rest of the scene unclipped.
+    pub fn pop_clip(&mut self) {
+        self.commands.push(DrawCommand::PopClip);
+    }
+}
+
+impl ClipStack {
+    /// The region drawing is limited to, or `None` when nothing is clipped.
+    pub fn current(&self) -> Option<Rect> {
+        self.clips.last().copied()
+    }
+
+    /// Limits drawing to the part of `rect` inside the current clip.
+    pub fn push(&mut self, rect: Rect) {
+        let clip = self.current().map_or(rect, |clip| clip.intersect(&rect));
+        self.clips.push(clip);
+    }
+
+    pub fn pop(&mut self) {
+        if self.clips.pop().is_none() {
+            log::warn!("pop_clip without a matching push_clip");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intersects_rects() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let b = Rect::new(5.0, 2.0, 10.0, 4.0);
+
+        assert_eq!(a.intersect(&b), Rect::new(5.0, 2.0, 5.0, 4.0));
+        assert_eq!(b.intersect(&a), a.intersect(&b));
+    }
+
+    #[test]
+    fn disjoint_rects_intersect_empty() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let b = Rect::new(20.0, 30.0, 5.0, 5.0);
+        let empty = a.intersect(&b);
+
+        assert_eq!(empty.width, 0.0);
+        assert_eq!(empty.height, 0.0);
+        assert!(!empty.contains(20.0, 30.0));
+    }
+
+    #[test]
+    fn nested_clips_intersect() {
+        let mut clips = ClipStack::default();
+        assert_eq!(clips.current(), None);
+
+        clips.push(Rect::new(0.0, 0.0, 100.0, 20.0));
+        clips.push(Rect::new(80.0, 10.0, 50.0, 50.0));
+        assert_eq!(clips.current(), Some(Rect::new(80.0, 10.0, 20.0, 10.0)));
+
+        // Clips only ever shrink, even when the inner one is larger.
+        clips.push(Rect::new(0.0, 0.0, 200.0, 200.0));
+        assert_eq!(clips.current(), Some(Rect::new(80.0, 10.0, 20.0, 10.0)));
+    }
+
+    #[test]
+    fn popping_restores_outer_clip() {
+        let mut clips = ClipStack::default();
+        clips.push(Rect::new(0.0, 0.0, 100.0, 20.0));
+        clips.push(Rect::new(10.0, 0.0, 10.0, 10.0));
+
+        clips.pop();
+        assert_eq!(clips.current(), Some(Rect::new(0.0, 0.0, 100.0, 20.0)));
+        clips.pop();
+        assert_eq!(clips.current(), None);
+    }
+
+    #[test]
+    fn unbalanced_pop_is_ignored() {
+        let mut clips = ClipStack::default();
+        clips.pop();
+        assert_eq!(clips.current(), None);
+
+        // The extra pop doesn't eat a later push.
+        clips.push(Rect::new(0.0, 0.0, 10.0, 10.0));
+        assert_eq!(clips.current(), Some(Rect::new(0.0, 0.0, 10.0, 10.0)));
+    }
+
+    #[test]
+    fn scene_records_clips_in_order() {
+        let mut scene = Scene::new();
+        scene.push_clip(Rect::new(0.0, 0.0, 10.0, 10.0));
+        scene.rect(
+            Rect::new(0.0, 0.0, 5.0, 5.0),
+            Color(1.0, 0.0, 0.0, 1.0),
+            Radius::default(),
+        );
+        scene.pop_clip();
+
+        assert!(matches!(
+            scene.commands(),
+            [
+                DrawCommand::PushClip(_),
+                DrawCommand::Quad(_),
+                DrawCommand::PopClip
+            ]
+        ));
+
+        scene.clear();
+        assert!(scene.is_empty());
+    }
+}
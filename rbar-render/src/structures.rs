@@ -1,21 +1,35 @@
-#![allow(dead_code)]
-
 use std::mem::size_of;
 
 use wgpu::{BufferAddress, VertexBufferLayout, VertexStepMode};
 
+use crate::images::Image;
+
+pub const FILL_SOLID: f32 = 0.0;
+pub const FILL_LINEAR_GRADIENT: f32 = 1.0;
+pub const FILL_RADIAL_GRADIENT: f32 = 2.0;
+pub const FILL_IMAGE: f32 = 3.0;
+
+/// Gradients are limited by how many vertex attributes a widget instance can use, which is why
+/// stop colors are packed into 8-bit sRGB.
+pub const MAX_GRADIENT_STOPS: usize = 8;
+
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Position(pub f32, pub f32);
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Color(pub f32, pub f32, pub f32, pub f32);
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Size(pub f32, pub f32);
 
+/// Corner radii in pixels: top-left, top-right, bottom-right, bottom-left.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Radius(pub f32, pub f32, pub f32, pub f32);
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Globals {
@@ -28,7 +42,59 @@ pub struct WidgetInstance {
     pub position: Position,
     pub size: Size,
     pub color: Color,
-    pub radius: f32,
+    pub radius: Radius,
+    pub border_color: Color,
+    pub outline_color: Color,
+    pub shadow_color: Color,
+    // The scalars below are uploaded four at a time, so their order must match `ATTRIBS`.
+    pub border_width: f32,
+    pub outline_width: f32,
+    pub outline_offset: f32,
+    pub shadow_blur: f32,
+    pub shadow_offset: Position,
+    pub shadow_spread: f32,
+    /// 1.0 for an inset shadow, 0.0 for an outset one.
+    pub shadow_inset: f32,
+    /// One of the `FILL_*` constants.
+    pub fill_kind: f32,
+    /// Direction of a linear gradient in radians, clockwise from "to top" like CSS.
+    pub gradient_angle: f32,
+    /// Center of a radial gradient, relative to the widget's top-left corner (0.0 to 1.0).
+    pub gradient_center: Position,
+    /// Offsets of the gradient stops along the gradient line (0.0 to 1.0).
+    pub gradient_offsets: [f32; MAX_GRADIENT_STOPS],
+    /// Colors of the gradient stops as straight-alpha sRGB bytes, red in the lowest byte.
+    pub gradient_colors: [u32; MAX_GRADIENT_STOPS],
+    /// Image atlas region (x, y, width, height in texels) used when `fill_kind` is `FILL_IMAGE`.
+    pub image_rect: [f32; 4],
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GradientStop {
+    pub offset: f32,
+    pub color: Color,
+}
+
+/// A CSS-style box shadow, see [`WidgetInstance::with_shadow`].
+#[derive(Copy, Clone, Debug)]
+pub struct Shadow {
+    pub color: Color,
+    pub offset: Position,
+    pub blur: f32,
+    pub spread: f32,
+    pub inset: bool,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GlyphInstance {
+    pub position: Position,
+    pub size: Size,
+    pub uv: Position,
+    pub color: Color,
+    pub is_color: u32,
+    /// Non-zero for glyphs turned 90° clockwise. `size` stays the bitmap's unrotated size.
+    pub rotated: u32,
 }
 
 impl Position {
@@ -54,20 +120,154 @@ impl From<wgpu::Color> for Color {
     }
 }
 
+impl Color {
+    /// A color from sRGB encoded components (0.0 to 1.0), as written in CSS or picked in most
+    /// color pickers, with straight `alpha`.
+    pub fn from_srgb(red: f32, green: f32, blue: f32, alpha: f32) -> Self {
+        Self(
+            srgb_to_linear(red),
+            srgb_to_linear(green),
+            srgb_to_linear(blue),
+            alpha,
+        )
+    }
+}
+
+impl Radius {
+    pub const fn uniform(radius: f32) -> Self {
+        Self(radius, radius, radius, radius)
+    }
+}
+
 impl WidgetInstance {
-    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+    const ATTRIBS: [wgpu::VertexAttribute; 15] = wgpu::vertex_attr_array![
         1 => Float32x2, // position: Location 1
         2 => Float32x2, // size: Location 2
         3 => Float32x4, // color: Location 3
-        4 => Float32,   // radius: Location 4
+        4 => Float32x4, // radius: Location 4
+        5 => Float32x4, // border_color: Location 5
+        6 => Float32x4, // outline_color: Location 6
+        7 => Float32x4, // shadow_color: Location 7
+        8 => Float32x4, // border_width, outline_width, outline_offset, shadow_blur: Location 8
+        9 => Float32x4, // shadow_offset, shadow_spread, shadow_inset: Location 9
+        10 => Float32x4, // fill_kind, gradient_angle, gradient_center: Location 10
+        11 => Float32x4, // gradient_offsets[0..4]: Location 11
+        12 => Float32x4, // gradient_offsets[4..8]: Location 12
+        13 => Uint32x4, // gradient_colors[0..4]: Location 13
+        14 => Uint32x4, // gradient_colors[4..8]: Location 14
+        15 => Float32x4, // image_rect: Location 15
     ];
 
-    pub fn new(x: f32, y: f32, width: f32, height: f32, color: Color, radius: f32) -> Self {
+    /// A solid quad whose top-left corner sits at `x`, `y`.
+    pub fn new(x: f32, y: f32, width: f32, height: f32, color: Color, radius: Radius) -> Self {
         Self {
             position: Position(x, y),
             size: Size(width, height),
             color,
             radius,
+            ..bytemuck::Zeroable::zeroed()
+        }
+    }
+
+    /// Draws a border of `width` pixels inside the widget's edge.
+    pub fn with_border(mut self, width: f32, color: Color) -> Self {
+        self.border_width = width;
+        self.border_color = color;
+        self
+    }
+
+    /// Draws an outline of `width` pixels, `offset` pixels outside the widget's edge.
+    pub fn with_outline(mut self, width: f32, offset: f32, color: Color) -> Self {
+        self.outline_width = width;
+        self.outline_offset = offset;
+        self.outline_color = color;
+        self
+    }
+
+    pub fn with_shadow(mut self, shadow: Shadow) -> Self {
+        self.shadow_color = shadow.color;
+        self.shadow_offset = shadow.offset;
+        self.shadow_blur = shadow.blur;
+        self.shadow_spread = shadow.spread;
+        self.shadow_inset = if shadow.inset { 1.0 } else { 0.0 };
+        self
+    }
+
+    /// Fills the widget with a linear gradient running at `angle` degrees, where 0 points up
+    /// and 90 points right.
+    pub fn with_linear_gradient(mut self, angle: f32, stops: &[GradientStop]) -> Self {
+        self.gradient_angle = angle.to_radians();
+        self.set_gradient(FILL_LINEAR_GRADIENT, stops);
+        self
+    }
+
+    /// Fills the widget with a circular gradient around `center`, reaching the farthest corner.
+    pub fn with_radial_gradient(mut self, center: Position, stops: &[GradientStop]) -> Self {
+        self.gradient_center = center;
+        self.set_gradient(FILL_RADIAL_GRADIENT, stops);
+        self
+    }
+
+    /// Fills the widget with `image`, stretched to its size. `color` tints the image.
+    pub fn with_image(mut self, image: &Image) -> Self {
+        self.fill_kind = FILL_IMAGE;
+        self.image_rect = [
+            image.region.x as f32,
+            image.region.y as f32,
+            image.region.width as f32,
+            image.region.height as f32,
+        ];
+        self
+    }
+
+    /// Returns the quad with every length multiplied by `scale`, to go from logical to physical
+    /// pixels.
+    pub fn scaled(mut self, scale: f32) -> Self {
+        self.position = Position(self.position.0 * scale, self.position.1 * scale);
+        self.size = Size(self.size.0 * scale, self.size.1 * scale);
+        self.radius = Radius(
+            self.radius.0 * scale,
+            self.radius.1 * scale,
+            self.radius.2 * scale,
+            self.radius.3 * scale,
+        );
+        self.border_width *= scale;
+        self.outline_width *= scale;
+        self.outline_offset *= scale;
+        self.shadow_blur *= scale;
+        self.shadow_offset = Position(self.shadow_offset.0 * scale, self.shadow_offset.1 * scale);
+        self.shadow_spread *= scale;
+        self
+    }
+
+    /// Switches to the gradient fill `kind` through `stops`. A single stop is a solid fill in
+    /// its color, and without stops the fill stays as it was.
+    fn set_gradient(&mut self, kind: f32, stops: &[GradientStop]) {
+        match stops {
+            [] => return,
+            [stop] => {
+                self.fill_kind = FILL_SOLID;
+                self.color = stop.color;
+                return;
+            }
+            _ => self.fill_kind = kind,
+        }
+
+        if stops.len() > MAX_GRADIENT_STOPS {
+            log::warn!(
+                "gradient has {} stops, only the first {MAX_GRADIENT_STOPS} are drawn",
+                stops.len()
+            );
+        }
+
+        // Unused slots repeat the last stop so the shader can always blend all of them.
+        let stop = |i: usize| stops[i.min(stops.len() - 1).min(MAX_GRADIENT_STOPS - 1)];
+
+        self.color = stops[0].color;
+
+        for i in 0..MAX_GRADIENT_STOPS {
+            self.gradient_offsets[i] = stop(i).offset;
+            self.gradient_colors[i] = pack_srgb(stop(i).color);
         }
     }
 
@@ -79,3 +279,132 @@ impl WidgetInstance {
         }
     }
 }
+
+impl GlyphInstance {
+    const ATTRIBS: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+        1 => Float32x2, // position: Location 1
+        2 => Float32x2, // size: Location 2
+        3 => Float32x2, // uv: Location 3
+        4 => Float32x4, // color: Location 4
+        5 => Uint32,    // is_color: Location 5
+        6 => Uint32,    // rotated: Location 6
+    ];
+
+    pub const fn descriptor() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Packs a linear color into straight-alpha sRGB bytes, red in the lowest byte.
+pub(crate) fn pack_srgb(color: Color) -> u32 {
+    let byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+
+    u32::from_le_bytes([
+        byte(linear_to_srgb(color.0)),
+        byte(linear_to_srgb(color.1)),
+        byte(linear_to_srgb(color.2)),
+        byte(color.3),
+    ])
+}
+
+pub(crate) fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color = Color(1.0, 0.0, 0.0, 1.0);
+    const BLUE: Color = Color(0.0, 0.0, 1.0, 1.0);
+
+    fn quad() -> WidgetInstance {
+        WidgetInstance::new(0.0, 0.0, 10.0, 10.0, BLUE, Radius::default())
+    }
+
+    fn stops(count: usize) -> Vec<GradientStop> {
+        (0..count)
+            .map(|i| GradientStop {
+                offset: i as f32 / (count - 1).max(1) as f32,
+                color: Color(i as f32 / count as f32, 0.0, 0.0, 1.0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gradient_without_stops_keeps_the_fill() {
+        let quad = quad().with_linear_gradient(90.0, &[]);
+
+        assert_eq!(quad.fill_kind, FILL_SOLID);
+        assert_eq!(quad.color, BLUE);
+    }
+
+    #[test]
+    fn gradient_with_one_stop_is_solid() {
+        let stop = GradientStop {
+            offset: 0.3,
+            color: RED,
+        };
+        let quad = quad().with_radial_gradient(Position(0.5, 0.5), &[stop]);
+
+        assert_eq!(quad.fill_kind, FILL_SOLID);
+        assert_eq!(quad.color, RED);
+    }
+
+    #[test]
+    fn unused_stops_repeat_the_last() {
+        let stops = stops(3);
+        let quad = quad().with_linear_gradient(90.0, &stops);
+
+        assert_eq!(quad.fill_kind, FILL_LINEAR_GRADIENT);
+        assert_eq!(quad.gradient_offsets[..3], [0.0, 0.5, 1.0]);
+        assert!(
+            quad.gradient_offsets[3..]
+                .iter()
+                .all(|&offset| offset == 1.0)
+        );
+        let last = pack_srgb(stops[2].color);
+        assert!(quad.gradient_colors[2..].iter().all(|&color| color == last));
+    }
+
+    #[test]
+    fn keeps_up_to_the_maximum_stops() {
+        let stops = stops(MAX_GRADIENT_STOPS + 2);
+        let quad = quad().with_linear_gradient(0.0, &stops);
+
+        for (i, stop) in stops[..MAX_GRADIENT_STOPS].iter().enumerate() {
+            assert_eq!(quad.gradient_offsets[i], stop.offset);
+            assert_eq!(quad.gradient_colors[i], pack_srgb(stop.color));
+        }
+    }
+
+    #[test]
+    fn packs_colors_as_srgb_bytes() {
+        assert_eq!(
+            pack_srgb(Color(1.0, 0.0, 0.0, 1.0)).to_le_bytes(),
+            [255, 0, 0, 255]
+        );
+        assert_eq!(
+            pack_srgb(Color::from_srgb(0.2, 0.4, 0.6, 0.5)).to_le_bytes(),
+            [51, 102, 153, 128]
+        );
+    }
+}
@@ -0,0 +1,401 @@
+use std::{collections::HashMap, fmt, path::Path};
+
+use anyhow::{Context, Result, anyhow};
+use cosmic_text::{
+    Attrs, Buffer, CacheKey, Family, FontSystem, Metrics, Shaping, Style, SwashCache, SwashContent,
+    Weight, fontdb,
+};
+use wgpu::{Device, Queue, TextureView};
+
+use crate::{
+    atlas::{Atlas, AtlasRegion},
+    structures::{Color, GlyphInstance, Position, Size},
+};
+
+const ATLAS_SIZE: u32 = 512;
+const LINE_HEIGHT: f32 = 1.2;
+
+/// Font selection for a [`TextRun`].
+///
+/// `family` accepts the generic names `sans-serif`, `serif` and `monospace` as well as any
+/// installed family name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Font {
+    pub family: String,
+    pub weight: u16,
+    pub italic: bool,
+}
+
+/// A piece of text queued for drawing, positioned by the top-left corner of its line box.
+#[derive(Clone, Debug)]
+pub struct TextRun {
+    pub text: String,
+    pub font: Font,
+    pub size: f32,
+    pub color: Color,
+    pub position: Position,
+    /// Turns the text 90° clockwise so it reads top to bottom, for vertical bars. The line box
+    /// turns with it, so `position` is still its top-left corner.
+    pub rotated: bool,
+}
+
+/// Shapes text exactly the way it will be drawn, so layout can size boxes around it.
+pub trait TextMeasure {
+    /// Returns the size of the line box `run` occupies.
+    fn measure_text(&self, run: &TextRun) -> Size;
+}
+
+/// A glyph placed by shaping, in physical pixels.
+pub struct ShapedGlyph {
+    pub key: CacheKey,
+    /// Pen position on the baseline, turned with the run if it is rotated.
+    pub x: i32,
+    pub y: i32,
+    pub color: Color,
+    pub rotated: bool,
+}
+
+/// A rasterized glyph as straight-alpha RGBA8 pixels.
+pub struct RasterGlyph {
+    pub width: u32,
+    pub height: u32,
+    /// Offset of the bitmap's top-left corner from the pen position, y pointing up.
+    pub left: i32,
+    pub top: i32,
+    pub pixels: Vec<u8>,
+    /// Color (emoji) glyphs keep their own colors instead of taking the run's.
+    pub is_color: bool,
+}
+
+/// Font discovery, shaping and glyph rasterization, independent of where glyphs end up.
+pub struct TextShaper {
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+}
+
+#[derive(Copy, Clone)]
+struct AtlasGlyph {
+    region: AtlasRegion,
+    left: i32,
+    top: i32,
+    is_color: bool,
+}
+
+/// Shaping and rasterization state shared by every surface.
+///
+/// Glyphs are rasterized once per cache key (font, glyph, size and subpixel bin) into a single
+/// atlas texture, so the same label on two outputs costs one upload.
+pub struct TextSystem {
+    shaper: TextShaper,
+    atlas: Atlas,
+    glyphs: HashMap<CacheKey, Option<AtlasGlyph>>,
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Self {
+            family: "sans-serif".to_string(),
+            weight: 400,
+            italic: false,
+        }
+    }
+}
+
+impl Font {
+    fn attrs(&self) -> Attrs<'_> {
+        let family = match self.family.as_str() {
+            "sans-serif" => Family::SansSerif,
+            "serif" => Family::Serif,
+            "monospace" => Family::Monospace,
+            name => Family::Name(name),
+        };
+        let style = if self.italic {
+            Style::Italic
+        } else {
+            Style::Normal
+        };
+
+        Attrs::new()
+            .family(family)
+            .weight(Weight(self.weight))
+            .style(style)
+    }
+}
+
+impl fmt::Debug for TextSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TextSystem")
+            .field("glyphs", &self.glyphs.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TextRun {
+    /// Returns the run with its size and position multiplied by `scale`.
+    pub fn scaled(&self, scale: f32) -> TextRun {
+        TextRun {
+            size: self.size * scale,
+            position: Position(self.position.0 * scale, self.position.1 * scale),
+            ..self.clone()
+        }
+    }
+}
+
+impl ShapedGlyph {
+    /// Where a bitmap `width` x `height` with the given bearings lands on screen, as its
+    /// top-left corner and size. Rotated glyphs are turned on their side.
+    pub fn placement(&self, left: i32, top: i32, width: u32, height: u32) -> (i32, i32, u32, u32) {
+        if self.rotated {
+            (self.x + top - height as i32, self.y + left, height, width)
+        } else {
+            (self.x + left, self.y - top, width, height)
+        }
+    }
+}
+
+impl TextShaper {
+    pub fn new() -> Self {
+        Self {
+            font_system: FontSystem::new(),
+            swash_cache: SwashCache::new(),
+        }
+    }
+
+    /// A shaper that only knows the font files in `fonts`, not the installed ones, with the
+    /// first font standing in for every generic family.
+    pub fn with_fonts(fonts: &[&Path]) -> Result<Self> {
+        let mut db = fontdb::Database::new();
+
+        for font in fonts {
+            db.load_font_file(font)
+                .with_context(|| format!("failed to load font {}", font.display()))?;
+        }
+
+        let family = db
+            .faces()
+            .next()
+            .and_then(|face| face.families.first())
+            .map(|(family, _)| family.clone())
+            .ok_or_else(|| anyhow!("no fonts to shape text with"))?;
+        db.set_sans_serif_family(family.clone());
+        db.set_serif_family(family.clone());
+        db.set_monospace_family(family);
+
+        Ok(Self {
+            font_system: FontSystem::new_with_locale_and_db("en-US".to_string(), db),
+            swash_cache: SwashCache::new(),
+        })
+    }
+
+    fn shape(&mut self, run: &TextRun) -> Buffer {
+        let metrics = Metrics::new(run.size, run.size * LINE_HEIGHT);
+        let mut buffer = Buffer::new(&mut self.font_system, metrics);
+        buffer.set_size(&mut self.font_system, None, None);
+        buffer.set_text(
+            &mut self.font_system,
+            &run.text,
+            &run.font.attrs(),
+            Shaping::Advanced,
+            None,
+        );
+        buffer.shape_until_scroll(&mut self.font_system, false);
+
+        buffer
+    }
+
+    /// Returns the size of the line box `run` would occupy once shaped, which is turned on its
+    /// side for rotated runs.
+    ///
+    /// Text is shaped at `scale` like it is drawn, since hinting makes glyph advances differ
+    /// between sizes, and the result is returned in logical pixels.
+    pub fn measure(&mut self, run: &TextRun, scale: f32) -> Size {
+        let size = line_box(&self.shape(&run.scaled(scale)));
+        let size = Size(size.0 / scale, size.1 / scale);
+
+        if run.rotated {
+            Size(size.1, size.0)
+        } else {
+            size
+        }
+    }
+
+    /// Shapes `run` and returns every glyph with its pen position and color.
+    pub fn glyphs(&mut self, run: &TextRun) -> Vec<ShapedGlyph> {
+        let buffer = self.shape(run);
+        let height = line_box(&buffer).1.round() as i32;
+        let origin = (run.position.0.round() as i32, run.position.1.round() as i32);
+        let mut glyphs = Vec::new();
+
+        for layout_run in buffer.layout_runs() {
+            for glyph in layout_run.glyphs {
+                // Rotated runs are laid out from the origin first, then turned around the
+                // top-left corner of the line box and moved back into place.
+                let offset = if run.rotated {
+                    (0.0, layout_run.line_y)
+                } else {
+                    (run.position.0, run.position.1 + layout_run.line_y)
+                };
+                let physical = glyph.physical(offset, 1.0);
+                let (x, y) = if run.rotated {
+                    (origin.0 + height - physical.y, origin.1 + physical.x)
+                } else {
+                    (physical.x, physical.y)
+                };
+                let color = match glyph.color_opt {
+                    Some(color) => Color(
+                        color.r() as f32 / 255.0,
+                        color.g() as f32 / 255.0,
+                        color.b() as f32 / 255.0,
+                        color.a() as f32 / 255.0,
+                    ),
+                    None => run.color,
+                };
+
+                glyphs.push(ShapedGlyph {
+                    key: physical.cache_key,
+                    x,
+                    y,
+                    color,
+                    rotated: run.rotated,
+                });
+            }
+        }
+
+        glyphs
+    }
+
+    /// Rasterizes the glyph behind `key`, or returns `None` for glyphs with nothing to draw.
+    pub fn rasterize(&mut self, key: CacheKey) -> Option<RasterGlyph> {
+        let image = self
+            .swash_cache
+            .get_image_uncached(&mut self.font_system, key)?;
+        let placement = image.placement;
+
+        if placement.width == 0 || placement.height == 0 {
+            return None;
+        }
+
+        let (pixels, is_color) = match image.content {
+            SwashContent::Mask => (
+                image
+                    .data
+                    .iter()
+                    .flat_map(|&coverage| [255, 255, 255, coverage])
+                    .collect::<Vec<u8>>(),
+                false,
+            ),
+            // Glyphs are stored with straight alpha, so collapse per-channel coverage into one
+            // value.
+            SwashContent::SubpixelMask => (
+                image
+                    .data
+                    .chunks_exact(4)
+                    .flat_map(|c| [255, 255, 255, c[0].max(c[1]).max(c[2])])
+                    .collect(),
+                false,
+            ),
+            SwashContent::Color => (image.data, true),
+        };
+
+        Some(RasterGlyph {
+            width: placement.width,
+            height: placement.height,
+            left: placement.left,
+            top: placement.top,
+            pixels,
+            is_color,
+        })
+    }
+}
+
+impl TextSystem {
+    pub fn new(device: &Device, shaper: TextShaper) -> Self {
+        Self {
+            shaper,
+            atlas: Atlas::new(device, "Glyph atlas", ATLAS_SIZE),
+            glyphs: HashMap::new(),
+        }
+    }
+
+    pub fn atlas_view(&self) -> &TextureView {
+        self.atlas.view()
+    }
+
+    pub fn atlas_generation(&self) -> u64 {
+        self.atlas.generation()
+    }
+
+    pub fn measure(&mut self, run: &TextRun, scale: f32) -> Size {
+        self.shaper.measure(run, scale)
+    }
+
+    /// Shapes `run` and appends one instance per visible glyph to `out`, rasterizing any glyph
+    /// that is not in the atlas yet.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        run: &TextRun,
+        out: &mut Vec<GlyphInstance>,
+    ) {
+        for glyph in self.shaper.glyphs(run) {
+            let Some(entry) = self.glyph(device, queue, glyph.key) else {
+                continue;
+            };
+
+            let (x, y, _, _) = glyph.placement(
+                entry.left,
+                entry.top,
+                entry.region.width,
+                entry.region.height,
+            );
+
+            out.push(GlyphInstance {
+                position: Position(x as f32, y as f32),
+                size: Size(entry.region.width as f32, entry.region.height as f32),
+                uv: Position(entry.region.x as f32, entry.region.y as f32),
+                color: glyph.color,
+                is_color: entry.is_color as u32,
+                rotated: glyph.rotated as u32,
+            });
+        }
+    }
+
+    fn glyph(&mut self, device: &Device, queue: &Queue, key: CacheKey) -> Option<AtlasGlyph> {
+        if let Some(entry) = self.glyphs.get(&key) {
+            return *entry;
+        }
+
+        let entry = self.rasterize(device, queue, key);
+        self.glyphs.insert(key, entry);
+
+        entry
+    }
+
+    fn rasterize(&mut self, device: &Device, queue: &Queue, key: CacheKey) -> Option<AtlasGlyph> {
+        let glyph = self.shaper.rasterize(key)?;
+        let region = self
+            .atlas
+            .allocate(device, queue, glyph.width, glyph.height)?;
+        self.atlas.upload(queue, region, &glyph.pixels);
+
+        Some(AtlasGlyph {
+            region,
+            left: glyph.left,
+            top: glyph.top,
+            is_color: glyph.is_color,
+        })
+    }
+}
+
+/// Size of the lines in `buffer`, unrotated.
+fn line_box(buffer: &Buffer) -> Size {
+    buffer
+        .layout_runs()
+        .fold(Size(0.0, 0.0), |size, layout_run| {
+            Size(
+                size.0.max(layout_run.line_w),
+                size.1 + layout_run.line_height,
+            )
+        })
+}
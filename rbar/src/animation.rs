@@ -0,0 +1,507 @@
+use std::time::{Duration, Instant};
+
+use rbar_render::Color;
+
+/// Residual displacement at which a spring counts as settled.
+const SPRING_REST: f32 = 0.001;
+
+/// Values that can be blended, so they can be animated.
+pub trait Lerp {
+    /// Returns the value `t` of the way from `self` to `to`. `t` may leave 0.0..=1.0 when an
+    /// easing overshoots.
+    fn lerp(&self, to: &Self, t: f32) -> Self;
+}
+
+/// How progress through an animation maps to progress between its values.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    /// A CSS `cubic-bezier(x1, y1, x2, y2)` timing function.
+    CubicBezier(f32, f32, f32, f32),
+    /// A damped spring released at the start value. It runs until it settles, which may
+    /// overshoot the target when underdamped.
+    Spring {
+        stiffness: f32,
+        damping: f32,
+        mass: f32,
+    },
+}
+
+/// An easing and how long it runs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transition {
+    pub duration: Duration,
+    pub easing: Easing,
+}
+
+/// A value that moves smoothly towards its target whenever the target changes.
+#[derive(Clone, Debug)]
+pub struct Animated<T> {
+    from: T,
+    to: T,
+    started: Option<Instant>,
+    transition: Transition,
+}
+
+/// Swings from 0.0 to 1.0 and back every period while active, for attention-grabbing states
+/// like urgent workspaces.
+#[derive(Copy, Clone, Debug)]
+pub struct Pulse {
+    period: Duration,
+    easing: Easing,
+    started: Option<Instant>,
+}
+
+impl Easing {
+    pub const EASE: Self = Self::CubicBezier(0.25, 0.1, 0.25, 1.0);
+    pub const EASE_IN: Self = Self::CubicBezier(0.42, 0.0, 1.0, 1.0);
+    pub const EASE_OUT: Self = Self::CubicBezier(0.0, 0.0, 0.58, 1.0);
+    pub const EASE_IN_OUT: Self = Self::CubicBezier(0.42, 0.0, 0.58, 1.0);
+
+    /// Maps linear progress `t` (0.0 to 1.0) to eased progress.
+    ///
+    /// For springs, `t` is the fraction of [`settle_time`](Self::settle_time) elapsed.
+    pub fn ease(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match *self {
+            Self::Linear => t,
+            Self::CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, t),
+            Self::Spring { .. } => match self.settle_time() {
+                Some(settle_time) if t < 1.0 => 1.0 - self.spring_displacement(t * settle_time),
+                _ => 1.0,
+            },
+        }
+    }
+
+    /// Seconds until a spring comes to rest, or `None` for easings that follow the
+    /// transition's duration.
+    pub fn settle_time(&self) -> Option<f32> {
+        let Self::Spring { .. } = self else {
+            return None;
+        };
+        let (omega, zeta) = self.spring_parameters();
+
+        // Whatever the damping, the displacement stays within e^(-decay t) (1 + decay t), where
+        // decay is the rate of the slowest decaying term. Solve for when that bound reaches the
+        // rest threshold, which a few fixed-point steps of u = ln((1 + u) / rest) do.
+        let decay = if zeta < 1.0 {
+            zeta * omega
+        } else {
+            omega * (zeta - (zeta * zeta - 1.0).sqrt())
+        };
+        let mut u = (1.0 / SPRING_REST).ln();
+
+        for _ in 0..8 {
+            u = ((1.0 + u) / SPRING_REST).ln();
+        }
+
+        Some(u / decay.max(f32::EPSILON))
+    }
+
+    /// Undamped angular frequency and damping ratio.
+    fn spring_parameters(&self) -> (f32, f32) {
+        let Self::Spring {
+            stiffness,
+            damping,
+            mass,
+        } = *self
+        else {
+            return (1.0, 1.0);
+        };
+        let stiffness = stiffness.max(f32::EPSILON);
+        let mass = mass.max(f32::EPSILON);
+
+        (
+            (stiffness / mass).sqrt(),
+            damping.max(0.0) / (2.0 * (stiffness * mass).sqrt()),
+        )
+    }
+
+    /// Distance from the target after `time` seconds, starting 1.0 away at rest.
+    fn spring_displacement(&self, time: f32) -> f32 {
+        let (omega, zeta) = self.spring_parameters();
+
+        if zeta < 1.0 {
+            let damped = omega * (1.0 - zeta * zeta).sqrt();
+
+            (-zeta * omega * time).exp()
+                * ((damped * time).cos() + zeta * omega / damped * (damped * time).sin())
+        } else if zeta == 1.0 {
+            (-omega * time).exp() * (1.0 + omega * time)
+        } else {
+            let root = (zeta * zeta - 1.0).sqrt();
+            let r1 = -omega * (zeta - root);
+            let r2 = -omega * (zeta + root);
+
+            (r2 * (r1 * time).exp() - r1 * (r2 * time).exp()) / (r2 - r1)
+        }
+    }
+}
+
+impl Transition {
+    /// Changes values immediately.
+    pub const NONE: Self = Self {
+        duration: Duration::ZERO,
+        easing: Easing::Linear,
+    };
+
+    pub const fn new(duration: Duration, easing: Easing) -> Self {
+        Self { duration, easing }
+    }
+
+    /// How long the transition runs. Springs run until they settle, whatever `duration` says.
+    pub fn length(&self) -> Duration {
+        match self.easing.settle_time() {
+            Some(seconds) => Duration::from_secs_f32(seconds),
+            None => self.duration,
+        }
+    }
+
+    /// Eased progress `elapsed` into the transition, 1.0 once it has finished.
+    pub fn progress(&self, elapsed: Duration) -> f32 {
+        let length = self.length();
+
+        if elapsed >= length || length.is_zero() {
+            return 1.0;
+        }
+
+        self.easing
+            .ease(elapsed.as_secs_f32() / length.as_secs_f32())
+    }
+}
+
+impl<T: Lerp + Clone + PartialEq> Animated<T> {
+    pub fn new(value: T, transition: Transition) -> Self {
+        Self {
+            from: value.clone(),
+            to: value,
+            started: None,
+            transition,
+        }
+    }
+
+    pub fn set_transition(&mut self, transition: Transition) {
+        self.transition = transition;
+    }
+
+    /// Starts moving from the current value to `target`. A change of direction mid-way
+    /// continues from wherever the value is now.
+    pub fn animate_to(&mut self, target: T, now: Instant) {
+        if target == self.to {
+            return;
+        }
+
+        self.from = self.value(now);
+        self.to = target;
+        self.started = Some(now);
+    }
+
+    /// Jumps to `value` without animating.
+    pub fn set(&mut self, value: T) {
+        self.from = value.clone();
+        self.to = value;
+        self.started = None;
+    }
+
+    pub fn value(&self, now: Instant) -> T {
+        match self.started {
+            Some(started) if self.is_animating(now) => {
+                let progress = self
+                    .transition
+                    .progress(now.saturating_duration_since(started));
+
+                self.from.lerp(&self.to, progress)
+            }
+            _ => self.to.clone(),
+        }
+    }
+
+    pub fn is_animating(&self, now: Instant) -> bool {
+        self.started.is_some_and(|started| {
+            now.saturating_duration_since(started) < self.transition.length()
+        })
+    }
+}
+
+impl Pulse {
+    pub const fn new(period: Duration, easing: Easing) -> Self {
+        Self {
+            period,
+            easing,
+            started: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Starts pulsing, unless already pulsing.
+    pub fn start(&mut self, now: Instant) {
+        self.started.get_or_insert(now);
+    }
+
+    pub fn stop(&mut self) {
+        self.started = None;
+    }
+
+    /// How far into the swing the pulse is: 0.0 when stopped, and at the start and end of
+    /// every period, 1.0 half way through.
+    pub fn amount(&self, now: Instant) -> f32 {
+        let Some(started) = self.started else {
+            return 0.0;
+        };
+
+        if self.period.is_zero() {
+            return 0.0;
+        }
+
+        let elapsed = now.saturating_duration_since(started).as_secs_f32();
+        let phase = (elapsed / self.period.as_secs_f32()).fract();
+        let swing = if phase < 0.5 {
+            phase * 2.0
+        } else {
+            2.0 - phase * 2.0
+        };
+
+        self.easing.ease(swing)
+    }
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Lerp for Color {
+    /// Blends premultiplied, so fading from a transparent color doesn't pass through black.
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        let alpha = self.3.lerp(&to.3, t);
+
+        if alpha <= 0.0 {
+            return Color(to.0, to.1, to.2, 0.0);
+        }
+
+        let channel =
+            |from: f32, to_channel: f32| (from * self.3).lerp(&(to_channel * to.3), t) / alpha;
+
+        Color(
+            channel(self.0, to.0),
+            channel(self.1, to.1),
+            channel(self.2, to.2),
+            alpha,
+        )
+    }
+}
+
+/// Evaluates a CSS cubic bezier timing function: finds the curve parameter whose x is `x`,
+/// then returns the y there.
+fn cubic_bezier(x1: f32, y1: f32, x2: f32, y2: f32, x: f32) -> f32 {
+    let curve = |a: f32, b: f32, s: f32| {
+        let inverse = 1.0 - s;
+        3.0 * inverse * inverse * s * a + 3.0 * inverse * s * s * b + s * s * s
+    };
+    let slope = |a: f32, b: f32, s: f32| {
+        let inverse = 1.0 - s;
+        3.0 * inverse * inverse * a + 6.0 * inverse * s * (b - a) + 3.0 * s * s * (1.0 - b)
+    };
+
+    // Newton's method converges in a few steps for well-behaved curves, fall back to bisection
+    // where the slope flattens out.
+    let mut s = x;
+
+    for _ in 0..8 {
+        let error = curve(x1, x2, s) - x;
+
+        if error.abs() < 1e-5 {
+            return curve(y1, y2, s);
+        }
+
+        let derivative = slope(x1, x2, s);
+
+        if derivative.abs() < 1e-6 {
+            break;
+        }
+
+        s -= error / derivative;
+    }
+
+    let (mut low, mut high) = (0.0, 1.0);
+    s = x;
+
+    for _ in 0..32 {
+        let value = curve(x1, x2, s);
+
+        if (value - x).abs() < 1e-5 {
+            break;
+        }
+
+        if value < x {
+            low = s;
+        } else {
+            high = s;
+        }
+
+        s = (low + high) / 2.0;
+    }
+
+    curve(y1, y2, s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESETS: [Easing; 4] = [
+        Easing::EASE,
+        Easing::EASE_IN,
+        Easing::EASE_OUT,
+        Easing::EASE_IN_OUT,
+    ];
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    fn spring(damping: f32) -> Easing {
+        Easing::Spring {
+            stiffness: 170.0,
+            damping,
+            mass: 1.0,
+        }
+    }
+
+    #[test]
+    fn cubic_bezier_hits_its_endpoints() {
+        for easing in PRESETS {
+            assert_close(easing.ease(0.0), 0.0);
+            assert_close(easing.ease(1.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn cubic_bezier_is_monotonic() {
+        for easing in PRESETS {
+            let values: Vec<f32> = (0..=100).map(|i| easing.ease(i as f32 / 100.0)).collect();
+
+            assert!(
+                values.windows(2).all(|pair| pair[1] >= pair[0] - 1e-5),
+                "{easing:?} goes backwards"
+            );
+        }
+    }
+
+    #[test]
+    fn cubic_bezier_matches_css() {
+        // Reference values of the CSS timing functions half way through.
+        assert_close(Easing::EASE.ease(0.5), 0.8024);
+        assert_close(Easing::EASE_IN.ease(0.5), 0.3153);
+        assert_close(Easing::EASE_OUT.ease(0.5), 0.6847);
+        assert_close(Easing::EASE_IN_OUT.ease(0.5), 0.5);
+        assert_close(Easing::CubicBezier(0.0, 0.0, 1.0, 1.0).ease(0.3), 0.3);
+    }
+
+    #[test]
+    fn springs_settle() {
+        for damping in [5.0, 26.0, 2.0 * 170.0_f32.sqrt(), 60.0] {
+            let easing = spring(damping);
+            let settle_time = easing.settle_time().unwrap();
+
+            assert!(easing.spring_displacement(settle_time).abs() <= SPRING_REST);
+            assert_eq!(easing.ease(1.0), 1.0);
+            assert_eq!(
+                Transition::new(Duration::from_millis(10), easing).length(),
+                Duration::from_secs_f32(settle_time)
+            );
+        }
+    }
+
+    #[test]
+    fn only_underdamped_springs_overshoot() {
+        let overshoots = |easing: Easing| (0..100).any(|i| easing.ease(i as f32 / 100.0) > 1.0);
+
+        assert!(overshoots(spring(5.0)));
+        assert!(!overshoots(spring(2.0 * 170.0_f32.sqrt())));
+        assert!(!overshoots(spring(60.0)));
+    }
+
+    #[test]
+    fn animates_towards_the_target() {
+        let start = Instant::now();
+        let linear = Transition::new(Duration::from_millis(100), Easing::Linear);
+        let mut value = Animated::new(0.0, linear);
+
+        value.animate_to(1.0, start);
+        assert_close(value.value(start + Duration::from_millis(25)), 0.25);
+        assert!(value.is_animating(start + Duration::from_millis(99)));
+        assert_eq!(value.value(start + Duration::from_millis(100)), 1.0);
+        assert!(!value.is_animating(start + Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn retargeting_mid_flight_continues_from_the_current_value() {
+        let start = Instant::now();
+        let linear = Transition::new(Duration::from_millis(100), Easing::Linear);
+        let mut value = Animated::new(0.0, linear);
+        value.animate_to(1.0, start);
+
+        let turn = start + Duration::from_millis(50);
+        value.animate_to(0.0, turn);
+
+        assert_close(value.value(turn), 0.5);
+        assert_close(value.value(turn + Duration::from_millis(50)), 0.25);
+        assert_eq!(value.value(turn + Duration::from_millis(100)), 0.0);
+    }
+
+    #[test]
+    fn animating_to_the_same_target_keeps_going() {
+        let start = Instant::now();
+        let linear = Transition::new(Duration::from_millis(100), Easing::Linear);
+        let mut value = Animated::new(0.0, linear);
+        value.animate_to(1.0, start);
+        value.animate_to(1.0, start + Duration::from_millis(50));
+
+        assert_close(value.value(start + Duration::from_millis(75)), 0.75);
+    }
+
+    #[test]
+    fn set_jumps_without_animating() {
+        let start = Instant::now();
+        let linear = Transition::new(Duration::from_millis(100), Easing::Linear);
+        let mut value = Animated::new(0.0, linear);
+        value.animate_to(1.0, start);
+        value.set(0.5);
+
+        assert!(!value.is_animating(start));
+        assert_eq!(value.value(start), 0.5);
+    }
+
+    #[test]
+    fn colors_blend_premultiplied() {
+        let transparent_red = Color(1.0, 0.0, 0.0, 0.0);
+        let blue = Color(0.0, 0.0, 1.0, 1.0);
+
+        assert_eq!(transparent_red.lerp(&blue, 0.5), Color(0.0, 0.0, 1.0, 0.5));
+        assert_eq!(blue.lerp(&transparent_red, 1.0), Color(1.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn pulses_swing_every_period() {
+        let start = Instant::now();
+        let mut pulse = Pulse::new(Duration::from_millis(100), Easing::Linear);
+        assert_eq!(pulse.amount(start), 0.0);
+
+        pulse.start(start);
+        assert_close(pulse.amount(start + Duration::from_millis(25)), 0.5);
+        assert_close(pulse.amount(start + Duration::from_millis(50)), 1.0);
+        assert_close(pulse.amount(start + Duration::from_millis(175)), 0.5);
+
+        pulse.stop();
+        assert_eq!(pulse.amount(start + Duration::from_millis(50)), 0.0);
+    }
+}
@@ -1,25 +1,36 @@
-use crate::bar::Bar;
-use anyhow::Result;
-use rbar_render::Renderer;
+use std::{path::PathBuf, rc::Rc, time::Instant};
+
+use crate::{
+    backend::{Backend, BarSurface},
+    backoff::Backoff,
+    bar::{
+        Bar,
+        stylesheet::{self, Stylesheet},
+    },
+    config::{self, BarConfig, Config},
+    ipc::Command,
+    scale::{ScaleState, SurfaceScale},
+    toplevel::ToplevelState,
+};
+use anyhow::{Context, Result, bail};
 use smithay_client_toolkit::{
     compositor::{CompositorHandler, CompositorState},
     output::{OutputHandler, OutputState},
     registry::{ProvidesRegistryState, RegistryState},
     registry_handlers,
     seat::{
-        SeatHandler, SeatState,
-        pointer::{PointerEvent, PointerHandler},
+        Capability, SeatHandler, SeatState,
+        pointer::{BTN_LEFT, PointerEvent, PointerEventKind, PointerHandler},
     },
     shell::{
         WaylandSurface,
-        wlr_layer::{
-            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
-            LayerSurfaceConfigure,
-        },
+        wlr_layer::{LayerShell, LayerShellHandler, LayerSurface, LayerSurfaceConfigure},
     },
+    shm::{Shm, ShmHandler},
 };
 use wayland_client::{
-    Connection, Proxy, QueueHandle,
+    Connection, QueueHandle,
+    globals::GlobalList,
     protocol::{
         wl_output::{Transform, WlOutput},
         wl_pointer::WlPointer,
@@ -32,85 +43,459 @@ pub struct App {
     seat_state: SeatState,
     layer_shell: LayerShell,
     bars: Vec<Bar>,
+    /// Outputs whose bar couldn't be created, with when to try again.
+    failed_outputs: Vec<(WlOutput, Backoff)>,
     compositor_state: CompositorState,
     registry_state: RegistryState,
-    renderer: Renderer,
+    shm: Shm,
+    scale_state: ScaleState,
+    toplevel_state: ToplevelState,
+    backend: Backend,
+    pointer: Option<WlPointer>,
+    stylesheet: Rc<Stylesheet>,
+    config: Config,
+    /// Set while the last reload of the configuration failed.
+    config_failed: bool,
 }
 
 impl App {
-    pub async fn new(
-        output_state: OutputState,
-        seat_state: SeatState,
-        layer_shell: LayerShell,
-        compositor_state: CompositorState,
-        registry_state: RegistryState,
-    ) -> Result<Self> {
+    /// Binds the globals rbar uses. Only the compositor, layer shell and shm ones are
+    /// required.
+    pub async fn new(globals: &GlobalList, qh: &QueueHandle<Self>) -> Result<Self> {
+        let config = Config::load_user()?;
+
         Ok(Self {
-            output_state,
-            seat_state,
-            layer_shell,
+            output_state: OutputState::new(globals, qh),
+            seat_state: SeatState::new(globals, qh),
+            layer_shell: LayerShell::bind(globals, qh)?,
             bars: vec![],
-            compositor_state,
-            registry_state,
-            renderer: Renderer::new().await?,
+            failed_outputs: vec![],
+            compositor_state: CompositorState::bind(globals, qh)?,
+            registry_state: RegistryState::new(globals),
+            shm: Shm::bind(globals, qh)?,
+            scale_state: ScaleState::bind(globals, qh),
+            toplevel_state: ToplevelState::bind(globals, qh),
+            backend: Backend::from_env().await?,
+            pointer: None,
+            stylesheet: Rc::new(Stylesheet::load_user(&config)?),
+            config,
+            config_failed: false,
         })
     }
-}
 
-impl OutputHandler for App {
-    fn output_state(&mut self) -> &mut OutputState {
-        &mut self.output_state
+    /// The files the configuration and stylesheet are loaded from, or would be if they
+    /// existed, and the files the stylesheet imports.
+    pub fn config_files(&self) -> Vec<PathBuf> {
+        let stylesheet = match &self.config.stylesheet {
+            Some(path) => Some(path.clone()),
+            None => stylesheet::user_path(),
+        };
+        let mut files: Vec<PathBuf> = [config::user_path(), stylesheet]
+            .into_iter()
+            .flatten()
+            .collect();
+
+        for file in self.stylesheet.files() {
+            if !files.contains(file) {
+                files.push(file.clone());
+            }
+        }
+
+        files
     }
 
-    fn new_output(&mut self, conn: &Connection, qh: &QueueHandle<Self>, output: WlOutput) {
+    /// Loads the configuration and stylesheet again and applies them, keeping the modules
+    /// that are still configured. If either fails to load, the bars keep the ones they have
+    /// and show that something is wrong until a reload succeeds.
+    pub fn reload(&mut self, conn: &Connection, qh: &QueueHandle<Self>) {
+        let loaded = Config::load_user().and_then(|config| {
+            let stylesheet = Stylesheet::load_user(&config)?;
+            Ok((config, stylesheet))
+        });
+        let (config, stylesheet) = match loaded {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                log::error!("failed to reload the configuration, keeping the last one: {error:#}");
+                self.config_failed = true;
+
+                for bar in &mut self.bars {
+                    let result = bar.set_config_error(qh, true);
+                    recover(&self.output_state, bar, "redraw", result);
+                }
+
+                return;
+            }
+        };
+
+        log::info!("reloaded the configuration");
+        self.config = config;
+        self.stylesheet = Rc::new(stylesheet);
+        self.config_failed = false;
+
+        // Rules for outputs may have changed which outputs get a bar. Outputs whose bar failed
+        // to be created pick up the new configuration on their next attempt.
+        for output in self.output_state.outputs() {
+            let config = self.bar_config(&output);
+            let index = self.bars.iter().position(|bar| bar.output == output);
+
+            match (index, config) {
+                (Some(index), Some(config)) => {
+                    let bar = &mut self.bars[index];
+                    let result = bar.reload(qh, config, self.stylesheet.clone());
+                    recover(&self.output_state, bar, "reconfigure", result);
+                }
+                (Some(index), None) => {
+                    log::info!(
+                        "removing the bar on output {}",
+                        output_name(&self.output_state, &output)
+                    );
+                    self.bars.remove(index);
+                }
+                (None, Some(config))
+                    if !self
+                        .failed_outputs
+                        .iter()
+                        .any(|(failed, _)| *failed == output) =>
+                {
+                    self.add_bar(conn, qh, output, config, Backoff::default());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn set_fractional_scale(
+        &mut self,
+        qh: &QueueHandle<Self>,
+        surface: &WlSurface,
+        scale: f64,
+    ) {
+        self.update_bar(surface, "rescale", |bar| {
+            bar.set_fractional_scale(qh, scale)
+        });
+    }
+
+    pub fn toplevel_state(&mut self) -> &mut ToplevelState {
+        &mut self.toplevel_state
+    }
+
+    /// Updates which bars have a fullscreen window on their output.
+    pub fn fullscreen_changed(&mut self, qh: &QueueHandle<Self>) {
+        for bar in &mut self.bars {
+            let covered = self.toplevel_state.is_fullscreen_on(&bar.output);
+            let result = bar.set_covered(qh, covered);
+            recover(&self.output_state, bar, "hide", result);
+        }
+    }
+
+    /// When [`wake`](Self::wake) has to be called next, to retry something that failed or
+    /// for a bar's timers.
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        let bars = self
+            .bars
+            .iter()
+            .flat_map(|bar| [bar.backoff.retry_at(), bar.next_wakeup()])
+            .flatten();
+        let outputs = self
+            .failed_outputs
+            .iter()
+            .filter_map(|(_, backoff)| backoff.retry_at());
+
+        bars.chain(outputs).min()
+    }
+
+    /// Retries whatever failed and is due for another attempt, and runs the bars' timers.
+    pub fn wake(&mut self, conn: &Connection, qh: &QueueHandle<Self>) {
+        let now = Instant::now();
+        let (due, waiting) = std::mem::take(&mut self.failed_outputs)
+            .into_iter()
+            .partition(|(_, backoff)| backoff.is_due(now));
+        self.failed_outputs = waiting;
+
+        for (output, backoff) in due {
+            if let Some(config) = self.bar_config(&output) {
+                self.add_bar(conn, qh, output, config, backoff);
+            }
+        }
+
+        for index in 0..self.bars.len() {
+            let bar = &mut self.bars[index];
+            let result = bar.tick(qh, now);
+            recover(&self.output_state, bar, "hide", result);
+
+            if !self.bars[index].backoff.is_due(now) {
+                continue;
+            }
+
+            if self.bars[index].is_closed() {
+                self.reopen_bar(conn, qh, index);
+                continue;
+            }
+
+            let bar = &mut self.bars[index];
+
+            match bar.retry(qh) {
+                Ok(()) => bar.backoff.reset(),
+                Err(error) => recover(&self.output_state, bar, "redraw", Err(error)),
+            }
+        }
+    }
+
+    /// Carries out a command received over IPC.
+    pub fn handle_command(&mut self, qh: &QueueHandle<Self>, command: &Command) -> Result<()> {
+        let now = Instant::now();
+        let (Command::Reveal { output } | Command::Hide { output }) = command;
+        let mut found = false;
+
+        for bar in &mut self.bars {
+            let name = output_name(&self.output_state, &bar.output);
+
+            if output.as_ref().is_some_and(|output| *output != name) {
+                continue;
+            }
+
+            found = true;
+
+            match command {
+                Command::Reveal { .. } => bar.reveal(now),
+                Command::Hide { .. } => bar.hide(now),
+            }
+
+            let result = bar.redraw(qh);
+            recover(&self.output_state, bar, "redraw", result);
+        }
+
+        match output {
+            Some(output) if !found => bail!("no bar on output {output}"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Gives the closed bar at `index` new surfaces, keeping its modules.
+    fn reopen_bar(&mut self, conn: &Connection, qh: &QueueHandle<Self>, index: usize) {
+        let bar = &self.bars[index];
+        let (output, config) = (bar.output.clone(), bar.config().clone());
+        let result = self.create_surfaces(conn, qh, &output, &config).map(
+            |(layer_surface, surface, scale)| {
+                self.bars[index].reattach(layer_surface, surface, scale)
+            },
+        );
+
+        recover(
+            &self.output_state,
+            &mut self.bars[index],
+            "recreate",
+            result,
+        );
+    }
+
+    /// Runs `update` on the bar on `surface`, retrying later if it fails.
+    fn update_bar(
+        &mut self,
+        surface: &WlSurface,
+        action: &str,
+        update: impl FnOnce(&mut Bar) -> Result<()>,
+    ) {
+        if let Some(bar) = self
+            .bars
+            .iter_mut()
+            .find(|bar| bar.layer_surface.wl_surface() == surface)
+        {
+            let result = update(bar);
+            recover(&self.output_state, bar, action, result);
+        }
+    }
+
+    /// The bar `output` should have according to the configuration, if any.
+    fn bar_config(&self, output: &WlOutput) -> Option<BarConfig> {
+        match self.output_state.info(output) {
+            Some(info) => self.config.bar_for(&info),
+            None => Some(self.config.default_bar()),
+        }
+    }
+
+    /// Gives `output` a bar, or schedules another attempt if that fails. `backoff` holds the
+    /// earlier failed attempts.
+    fn add_bar(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        output: WlOutput,
+        config: BarConfig,
+        mut backoff: Backoff,
+    ) {
+        if let Err(error) = self.create_bar(conn, qh, output.clone(), config) {
+            let delay = backoff.failed(Instant::now());
+            log::error!(
+                "failed to create the bar on output {}, retrying in {delay:?}: {error:#}",
+                output_name(&self.output_state, &output)
+            );
+            self.failed_outputs.push((output, backoff));
+        }
+    }
+
+    fn create_bar(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        output: WlOutput,
+        config: BarConfig,
+    ) -> Result<()> {
+        let (layer_surface, surface, scale) = self.create_surfaces(conn, qh, &output, &config)?;
+
+        let covered = self.toplevel_state.is_fullscreen_on(&output);
+        let mut bar = Bar::new(
+            layer_surface,
+            output,
+            surface,
+            scale,
+            self.stylesheet.clone(),
+            config,
+        )?;
+        bar.set_config_error(qh, self.config_failed)?;
+        bar.set_covered(qh, covered)?;
+        self.bars.push(bar);
+
+        Ok(())
+    }
+
+    /// The layer surface of a bar on `output` and what it is drawn with.
+    fn create_surfaces(
+        &self,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        output: &WlOutput,
+        config: &BarConfig,
+    ) -> Result<(LayerSurface, BarSurface, SurfaceScale)> {
         let surface = self.compositor_state.create_surface(qh);
-        let surface_id = surface.id();
         let layer_surface = self.layer_shell.create_layer_surface(
             qh,
             surface,
-            Layer::Top,
+            config.layer.into(),
             Some("rbar"),
-            Some(&output),
+            Some(output),
         );
 
-        layer_surface.set_anchor(Anchor::TOP | Anchor::LEFT | Anchor::RIGHT);
-        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
-        layer_surface.set_size(0, 30);
-        layer_surface.set_exclusive_zone(30);
-        layer_surface.commit();
-        let surface_renderer =
-            self.renderer
-                .create_surface_renderer(&conn.backend(), surface_id, 100, 100);
+        let surface = self
+            .backend
+            .create_surface(conn, &self.shm, layer_surface.wl_surface(), 100, 100)
+            .context("failed to create the drawing surface")?;
+        let integer_scale = self
+            .output_state
+            .info(output)
+            .map_or(1, |info| info.scale_factor);
+        let scale = self
+            .scale_state
+            .track(qh, layer_surface.wl_surface(), integer_scale);
 
-        self.bars
-            .push(Bar::new(layer_surface, output, surface_renderer).unwrap());
+        Ok((layer_surface, surface, scale))
     }
+}
 
-    fn update_output(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _output: WlOutput) {}
+/// Logs a failed `action` on `bar` and schedules a retry.
+fn recover(output_state: &OutputState, bar: &mut Bar, action: &str, result: Result<()>) {
+    if let Err(error) = result {
+        let delay = bar.backoff.failed(Instant::now());
+        log::error!(
+            "failed to {action} the bar on output {}, retrying in {delay:?}: {error:#}",
+            output_name(output_state, &bar.output)
+        );
+    }
+}
+
+fn output_name(output_state: &OutputState, output: &WlOutput) -> String {
+    output_state
+        .info(output)
+        .and_then(|info| info.name)
+        .unwrap_or_else(|| "<unnamed>".to_string())
+}
+
+impl OutputHandler for App {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(&mut self, conn: &Connection, qh: &QueueHandle<Self>, output: WlOutput) {
+        match self.bar_config(&output) {
+            Some(config) => self.add_bar(conn, qh, output, config, Backoff::default()),
+            None => log::info!(
+                "no bar on output {}",
+                output_name(&self.output_state, &output)
+            ),
+        }
+    }
+
+    fn update_output(&mut self, conn: &Connection, qh: &QueueHandle<Self>, output: WlOutput) {
+        let config = self.bar_config(&output);
+        let index = self.bars.iter().position(|bar| bar.output == output);
+
+        match (index, config) {
+            (Some(index), Some(config)) => {
+                let scale = self
+                    .output_state
+                    .info(&output)
+                    .map_or(1, |info| info.scale_factor);
+                let bar = &mut self.bars[index];
+                let result = bar.output_changed(qh, config, scale);
+                recover(&self.output_state, bar, "reconfigure", result);
+            }
+            (Some(index), None) => {
+                log::info!(
+                    "removing the bar on output {}",
+                    output_name(&self.output_state, &output)
+                );
+                self.bars.remove(index);
+            }
+            (None, Some(config)) => {
+                // Try right away, the change may be what made the last attempt fail.
+                let backoff = self
+                    .failed_outputs
+                    .iter()
+                    .position(|(failed, _)| *failed == output)
+                    .map(|index| self.failed_outputs.remove(index).1)
+                    .unwrap_or_default();
+                self.add_bar(conn, qh, output, config, backoff);
+            }
+            (None, None) => {}
+        }
+    }
 
     fn output_destroyed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, output: WlOutput) {
         self.bars.retain(|bar| bar.output != output);
+        self.failed_outputs.retain(|(failed, _)| *failed != output);
     }
 }
 
 impl LayerShellHandler for App {
+    /// Compositors close layer surfaces when outputs power off, on some mode switches and
+    /// when they reload. Bars then get new surfaces after a delay, which grows if the
+    /// compositor keeps closing them, and keep their modules meanwhile.
     fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, layer: &LayerSurface) {
-        self.bars.retain(|bar| &bar.layer_surface != layer);
+        if let Some(bar) = self.bars.iter_mut().find(|bar| &bar.layer_surface == layer) {
+            bar.close();
+            let delay = bar.backoff.failed(Instant::now());
+            log::warn!(
+                "the compositor closed the bar on output {}, recreating it in {delay:?}",
+                output_name(&self.output_state, &bar.output)
+            );
+        }
     }
 
     fn configure(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
         layer: &LayerSurface,
         configure: LayerSurfaceConfigure,
         _serial: u32,
     ) {
         let (width, height) = configure.new_size;
-
-        if let Some(bar) = self.bars.iter_mut().find(|bar| &bar.layer_surface == layer) {
-            bar.configure(width, height).unwrap();
-        }
+        // A configured bar works again, so any earlier failures are forgotten.
+        self.update_bar(layer.wl_surface(), "configure", |bar| {
+            bar.configure(qh, width, height)?;
+            bar.backoff.reset();
+            Ok(())
+        });
     }
 }
 
@@ -118,31 +503,42 @@ impl CompositorHandler for App {
     fn scale_factor_changed(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _surface: &WlSurface,
-        _new_factor: i32,
+        qh: &QueueHandle<Self>,
+        surface: &WlSurface,
+        new_factor: i32,
     ) {
-        // Handle scale factor changes if needed
+        self.update_bar(surface, "rescale", |bar| {
+            bar.set_integer_scale(qh, new_factor)
+        });
     }
 
     fn transform_changed(
         &mut self,
-        _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _surface: &WlSurface,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        surface: &WlSurface,
         _new_transform: Transform,
     ) {
-        // Handle transform changes if needed
+        // The compositor rotated the output under the bar, which is caught up with like any
+        // other change to its output.
+        let output = self
+            .bars
+            .iter()
+            .find(|bar| bar.layer_surface.wl_surface() == surface)
+            .map(|bar| bar.output.clone());
+        if let Some(output) = output {
+            self.update_output(conn, qh, output);
+        }
     }
 
     fn frame(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _surface: &WlSurface,
+        qh: &QueueHandle<Self>,
+        surface: &WlSurface,
         _time: u32,
     ) {
-        // Handle frame callbacks if needed for animations
+        self.update_bar(surface, "draw", |bar| bar.frame_done(qh));
     }
 
     fn surface_enter(
@@ -166,22 +562,59 @@ impl CompositorHandler for App {
     }
 }
 
+impl ShmHandler for App {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
 impl ProvidesRegistryState for App {
     fn registry(&mut self) -> &mut smithay_client_toolkit::registry::RegistryState {
         &mut self.registry_state
     }
 
-    registry_handlers![OutputState];
+    registry_handlers![OutputState, SeatState];
 }
 
 impl PointerHandler for App {
     fn pointer_frame(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
         _pointer: &WlPointer,
         events: &[PointerEvent],
     ) {
+        let now = Instant::now();
+
+        for event in events {
+            let Some(bar) = self
+                .bars
+                .iter_mut()
+                .find(|bar| bar.layer_surface.wl_surface() == &event.surface)
+            else {
+                continue;
+            };
+
+            match event.kind {
+                PointerEventKind::Enter { .. } | PointerEventKind::Motion { .. } => {
+                    bar.pointer_moved(Some(event.position), now);
+                }
+                PointerEventKind::Leave { .. } => {
+                    bar.pointer_moved(None, now);
+                    bar.pointer_pressed(false);
+                }
+                PointerEventKind::Press { button, .. } if button == BTN_LEFT => {
+                    bar.pointer_pressed(true)
+                }
+                PointerEventKind::Release { button, .. } if button == BTN_LEFT => {
+                    bar.pointer_pressed(false)
+                }
+                _ => continue,
+            }
+
+            let result = bar.redraw(qh);
+            recover(&self.output_state, bar, "redraw", result);
+        }
     }
 }
 
@@ -192,35 +625,46 @@ impl SeatHandler for App {
 
     fn new_seat(
         &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
-        seat: wayland_client::protocol::wl_seat::WlSeat,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _seat: wayland_client::protocol::wl_seat::WlSeat,
     ) {
     }
 
     fn new_capability(
         &mut self,
-        conn: &Connection,
+        _conn: &Connection,
         qh: &QueueHandle<Self>,
         seat: wayland_client::protocol::wl_seat::WlSeat,
-        capability: smithay_client_toolkit::seat::Capability,
+        capability: Capability,
     ) {
+        if capability == Capability::Pointer && self.pointer.is_none() {
+            match self.seat_state.get_pointer(qh, &seat) {
+                Ok(pointer) => self.pointer = Some(pointer),
+                Err(error) => log::warn!("failed to get the pointer: {error}"),
+            }
+        }
     }
 
     fn remove_capability(
         &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
-        seat: wayland_client::protocol::wl_seat::WlSeat,
-        capability: smithay_client_toolkit::seat::Capability,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _seat: wayland_client::protocol::wl_seat::WlSeat,
+        capability: Capability,
     ) {
+        if capability == Capability::Pointer
+            && let Some(pointer) = self.pointer.take()
+        {
+            pointer.release();
+        }
     }
 
     fn remove_seat(
         &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
-        seat: wayland_client::protocol::wl_seat::WlSeat,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _seat: wayland_client::protocol::wl_seat::WlSeat,
     ) {
     }
 }
@@ -231,3 +675,4 @@ smithay_client_toolkit::delegate_compositor!(App);
 smithay_client_toolkit::delegate_registry!(App);
 smithay_client_toolkit::delegate_pointer!(App);
 smithay_client_toolkit::delegate_seat!(App);
+smithay_client_toolkit::delegate_shm!(App);
@@ -0,0 +1,201 @@
+use anyhow::{Context, Result, bail};
+use rbar_render::{
+    CpuCanvas, CpuRenderer, Rect, Renderer, Scene, Size, SurfaceRenderer, TextMeasure, TextRun,
+};
+use smithay_client_toolkit::shm::{
+    Shm,
+    slot::{Buffer, SlotPool},
+};
+use wayland_client::{
+    Connection, Proxy,
+    protocol::{wl_shm::Format, wl_surface::WlSurface},
+};
+
+/// Environment variable selecting the renderer: `auto` (the default), `gpu` or `cpu`.
+const RENDERER_VAR: &str = "RBAR_RENDERER";
+
+/// Draws every bar, either with wgpu or on the CPU into shared-memory buffers.
+pub enum Backend {
+    Gpu(Renderer),
+    Cpu(CpuRenderer),
+}
+
+/// The drawing target of one bar.
+// There is one per bar, so the size difference between variants does not matter.
+#[allow(clippy::large_enum_variant)]
+pub enum BarSurface {
+    Gpu(GpuSurface),
+    Shm(ShmSurface),
+}
+
+/// A wgpu surface, along with the `wl_surface` it presents to so damage can be reported.
+pub struct GpuSurface {
+    surface: WlSurface,
+    renderer: SurfaceRenderer,
+}
+
+/// A CPU canvas presented through `wl_shm` buffers.
+pub struct ShmSurface {
+    surface: WlSurface,
+    canvas: CpuCanvas,
+    pool: SlotPool,
+    buffer: Option<Buffer>,
+}
+
+impl Backend {
+    /// Picks the renderer named by `RBAR_RENDERER`. With `auto`, rbar falls back to the CPU
+    /// renderer when no GPU adapter is available, such as in VMs without acceleration.
+    pub async fn from_env() -> Result<Self> {
+        let choice = std::env::var(RENDERER_VAR).ok();
+
+        match choice.as_deref().unwrap_or("auto") {
+            "gpu" => Ok(Self::Gpu(Renderer::new().await?)),
+            "cpu" => Ok(Self::Cpu(CpuRenderer::new())),
+            "auto" => match Renderer::new().await {
+                Ok(renderer) => Ok(Self::Gpu(renderer)),
+                Err(error) => {
+                    log::warn!("no usable GPU ({error:#}), falling back to the CPU renderer");
+                    Ok(Self::Cpu(CpuRenderer::new()))
+                }
+            },
+            other => bail!("{RENDERER_VAR} must be auto, gpu or cpu, not {other:?}"),
+        }
+    }
+
+    pub fn create_surface(
+        &self,
+        conn: &Connection,
+        shm: &Shm,
+        surface: &WlSurface,
+        width: u32,
+        height: u32,
+    ) -> Result<BarSurface> {
+        Ok(match self {
+            Self::Gpu(renderer) => BarSurface::Gpu(GpuSurface {
+                surface: surface.clone(),
+                renderer: renderer.create_surface_renderer(
+                    &conn.backend(),
+                    surface.id(),
+                    width,
+                    height,
+                )?,
+            }),
+            Self::Cpu(renderer) => BarSurface::Shm(ShmSurface {
+                surface: surface.clone(),
+                canvas: renderer.create_canvas(width, height),
+                pool: SlotPool::new((width * height * 4) as usize, shm)
+                    .context("failed to create shm pool")?,
+                buffer: None,
+            }),
+        })
+    }
+}
+
+impl BarSurface {
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        match self {
+            Self::Gpu(gpu) => gpu.renderer.set_size(width, height),
+            Self::Shm(shm) => shm.canvas.set_size(width, height),
+        }
+    }
+
+    pub fn set_scale(&mut self, scale: f32) {
+        match self {
+            Self::Gpu(gpu) => gpu.renderer.set_scale(scale),
+            Self::Shm(shm) => shm.canvas.set_scale(scale),
+        }
+    }
+
+    /// Draws `scene` and presents it, telling the compositor only `damage` changed.
+    ///
+    /// `damage` is in logical pixels, and both backends only draw those areas again.
+    pub fn render(&mut self, scene: &Scene, damage: &[Rect]) -> Result<()> {
+        match self {
+            Self::Gpu(gpu) => gpu.render(scene, damage),
+            Self::Shm(shm) => shm.render(scene, damage),
+        }
+    }
+}
+
+impl TextMeasure for BarSurface {
+    fn measure_text(&self, run: &TextRun) -> Size {
+        match self {
+            Self::Gpu(gpu) => gpu.renderer.measure_text(run),
+            Self::Shm(shm) => shm.canvas.measure_text(run),
+        }
+    }
+}
+
+impl GpuSurface {
+    fn render(&mut self, scene: &Scene, damage: &[Rect]) -> Result<()> {
+        // Requests on the wl_surface go out in order, so this damage is part of the commit
+        // wgpu makes when presenting. Drivers that don't know which parts of a frame changed,
+        // like Mesa's Vulkan WSI without incremental present, add damage for the whole surface
+        // on top of it.
+        for area in self.renderer.damaged_pixels(damage) {
+            self.surface.damage_buffer(
+                area.x as i32,
+                area.y as i32,
+                area.width as i32,
+                area.height as i32,
+            );
+        }
+
+        self.renderer.render_damaged(scene, damage)
+    }
+}
+
+impl ShmSurface {
+    fn render(&mut self, scene: &Scene, damage: &[Rect]) -> Result<()> {
+        let width = self.canvas.width() as i32;
+        let height = self.canvas.height() as i32;
+        let stride = width * 4;
+
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        // The canvas keeps the last frame, so only the damaged areas need rasterizing again.
+        let damage: Vec<Rect> = damage
+            .iter()
+            .map(|area| self.canvas.render_area(scene, *area))
+            .collect();
+
+        // Reuse the last buffer once the compositor has released it, otherwise draw into a
+        // fresh one so a frame never tears.
+        let buffer = match self.buffer.take() {
+            Some(buffer)
+                if buffer.height() == height
+                    && buffer.stride() == stride
+                    && self.pool.canvas(&buffer).is_some() =>
+            {
+                buffer
+            }
+            _ => {
+                self.pool
+                    .create_buffer(width, height, stride, Format::Argb8888)?
+                    .0
+            }
+        };
+        let pixels = self
+            .pool
+            .canvas(&buffer)
+            .context("shm buffer is still in use")?;
+        self.canvas.write_argb8888(pixels);
+
+        for area in damage {
+            self.surface.damage_buffer(
+                area.x as i32,
+                area.y as i32,
+                area.width as i32,
+                area.height as i32,
+            );
+        }
+
+        buffer.attach_to(&self.surface)?;
+        self.surface.commit();
+        self.buffer = Some(buffer);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use std::time::{Duration, Instant};
+
+/// Delay before the first retry, doubled after every further failure.
+const INITIAL_DELAY: Duration = Duration::from_millis(500);
+/// Longest delay between retries.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// When to retry something that keeps failing, waiting twice as long after each failure.
+#[derive(Clone, Debug, Default)]
+pub struct Backoff {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    /// Records a failure at `now` and returns how long to wait before retrying.
+    pub fn failed(&mut self, now: Instant) -> Duration {
+        let delay = INITIAL_DELAY
+            .saturating_mul(1 << self.failures.min(16))
+            .min(MAX_DELAY);
+        self.failures += 1;
+        self.retry_at = Some(now + delay);
+
+        delay
+    }
+
+    /// Forgets earlier failures, after a retry succeeded.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// When the next retry is due, if one is.
+    pub fn retry_at(&self) -> Option<Instant> {
+        self.retry_at
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.retry_at.is_some_and(|retry_at| retry_at <= now)
+    }
+}
@@ -0,0 +1,743 @@
+//! Flexbox layout for [`Node`] trees.
+//!
+//! This follows the CSS flexbox algorithm for a single line: children keep their intrinsic
+//! size along the main axis, leftover space is handed out by `grow` and overflow taken back by
+//! `shrink` (weighted by size), both within min/max limits, and what is still left over is
+//! distributed by `justify`. Text is measured by shaping it, so boxes fit their content.
+//!
+//! Text runs along its inline axis, which is vertical for sideways and upright
+//! [`TextOrientation`]s: alignment and ellipsizing happen along it, and upright text is drawn
+//! one character per line, each centered across the text.
+
+use rbar_render::{Color, Position, Rect, Scene, Size, TextMeasure, TextRun, WidgetInstance};
+
+use crate::bar::{
+    node::Node,
+    style::{
+        Align, ComputedStyle, Direction, Edges, Justify, Length, Overflow, TextAlign,
+        TextOrientation,
+    },
+};
+
+const ELLIPSIS: char = '\u{2026}';
+
+/// Where a node and its descendants ended up, in logical pixels.
+#[derive(Clone, Debug, Default)]
+pub struct Layout {
+    /// The border box.
+    pub rect: Rect,
+    /// The border box minus border and padding, where text is drawn.
+    pub content: Rect,
+    /// The style the node computed to.
+    pub style: ComputedStyle,
+    /// One layout per child, in the same order.
+    pub children: Vec<Layout>,
+}
+
+/// A child being sized along its parent's main axis.
+struct FlexItem {
+    /// Size before flexing.
+    base: f32,
+    /// Size after flexing.
+    size: f32,
+    min: f32,
+    max: f32,
+    grow: f32,
+    shrink: f32,
+    /// Margins before and after the item along the main axis.
+    margin: (f32, f32),
+    frozen: bool,
+}
+
+/// Lays out `node` and its descendants to fill `bounds`.
+pub fn layout(node: &Node, bounds: Rect, measure: &impl TextMeasure) -> Layout {
+    arrange(
+        node,
+        node.style.compute(&ComputedStyle::default()),
+        bounds,
+        measure,
+    )
+}
+
+/// Adds `node` and its descendants to `scene` where `layout` put them.
+pub fn draw(scene: &mut Scene, node: &Node, layout: &Layout, measure: &impl TextMeasure) {
+    draw_node(scene, node, layout, 1.0, measure);
+}
+
+/// The run drawing `text` in `style` at `position`. Upright text takes one run per
+/// character, see [`text_runs`].
+pub fn text_run(text: &str, style: &ComputedStyle, position: Position) -> TextRun {
+    TextRun {
+        text: text.to_string(),
+        font: style.font(),
+        size: style.font_size,
+        color: style.foreground,
+        position,
+        rotated: style.text_orientation == TextOrientation::Sideways,
+    }
+}
+
+/// The size of `text` drawn in `style`.
+pub fn text_size(text: &str, style: &ComputedStyle, measure: &impl TextMeasure) -> Size {
+    if style.text_orientation != TextOrientation::Upright {
+        return measure.measure_text(&text_run(text, style, Position(0.0, 0.0)));
+    }
+
+    text.chars().fold(Size(0.0, 0.0), |size, character| {
+        let line = measure.measure_text(&text_run(
+            character.encode_utf8(&mut [0; 4]),
+            style,
+            Position(0.0, 0.0),
+        ));
+        Size(size.0.max(line.0), size.1 + line.1)
+    })
+}
+
+/// The runs drawing `text` in `style` with the top-left corner at `position`.
+pub fn text_runs(
+    text: &str,
+    style: &ComputedStyle,
+    position: Position,
+    measure: &impl TextMeasure,
+) -> Vec<TextRun> {
+    if style.text_orientation != TextOrientation::Upright {
+        return vec![text_run(text, style, position)];
+    }
+
+    let width = text_size(text, style, measure).0;
+    let mut y = position.1;
+
+    text.chars()
+        .map(|character| {
+            let mut run = text_run(character.encode_utf8(&mut [0; 4]), style, position);
+            let size = measure.measure_text(&run);
+            run.position = Position(position.0 + (width - size.0) / 2.0, y);
+            y += size.1;
+            run
+        })
+        .collect()
+}
+
+/// The size `node`, computed to `style`, takes when given all the room it wants: its fixed
+/// size if it has one, otherwise its content plus border and padding, kept within its min/max
+/// limits.
+pub fn intrinsic_size(node: &Node, style: &ComputedStyle, measure: &impl TextMeasure) -> Size {
+    let content = if !node.is_leaf() {
+        let direction = style.direction;
+        let gaps = style.gap * node.children.len().saturating_sub(1) as f32;
+        let (main_size, cross_size) =
+            node.children
+                .iter()
+                .fold((gaps, 0.0f32), |(main_size, cross_size), child| {
+                    let child_style = child.style.compute(style);
+                    let size = intrinsic_size(child, &child_style, measure);
+                    let margin = &child_style.margin;
+                    let outer = Size(size.0 + margin.horizontal(), size.1 + margin.vertical());
+
+                    (
+                        main_size + main(direction, outer),
+                        cross_size.max(cross(direction, outer)),
+                    )
+                });
+
+        match direction {
+            Direction::Row => Size(main_size, cross_size),
+            Direction::Column => Size(cross_size, main_size),
+        }
+    } else if node.content.is_empty() {
+        Size(0.0, 0.0)
+    } else {
+        text_size(&node.content, style, measure)
+    };
+    let insets = style.insets();
+
+    Size(
+        clamp(
+            style.width.px().unwrap_or(content.0 + insets.horizontal()),
+            style.min_width,
+            style.max_width,
+        ),
+        clamp(
+            style.height.px().unwrap_or(content.1 + insets.vertical()),
+            style.min_height,
+            style.max_height,
+        ),
+    )
+}
+
+fn arrange(node: &Node, style: ComputedStyle, bounds: Rect, measure: &impl TextMeasure) -> Layout {
+    let content = inset(bounds, &style.insets());
+
+    if node.is_leaf() {
+        return Layout {
+            rect: bounds,
+            content,
+            style,
+            children: vec![],
+        };
+    }
+
+    let direction = style.direction;
+    let main_space = main(direction, Size(content.width, content.height));
+    let cross_space = cross(direction, Size(content.width, content.height));
+    let styles: Vec<ComputedStyle> = node
+        .children
+        .iter()
+        .map(|child| child.style.compute(&style))
+        .collect();
+    let intrinsic: Vec<Size> = node
+        .children
+        .iter()
+        .zip(&styles)
+        .map(|(child, child_style)| intrinsic_size(child, child_style, measure))
+        .collect();
+    let mut items: Vec<FlexItem> = styles
+        .iter()
+        .zip(&intrinsic)
+        .map(|(child_style, size)| {
+            let (min, max) = main_limits(direction, child_style);
+            let base = match child_style.basis {
+                Length::Auto => main(direction, *size),
+                Length::Px(basis) => clamp(basis, min, max),
+            };
+
+            FlexItem {
+                base,
+                size: base,
+                min,
+                max,
+                grow: child_style.grow,
+                shrink: child_style.shrink,
+                margin: main_margins(direction, &child_style.margin),
+                frozen: false,
+            }
+        })
+        .collect();
+    let gaps = style.gap * items.len().saturating_sub(1) as f32;
+
+    resolve_flexible_lengths(&mut items, main_space - gaps);
+
+    let used = gaps
+        + items
+            .iter()
+            .map(|item| item.size + item.margin.0 + item.margin.1)
+            .sum::<f32>();
+    let (mut cursor, spacing) = justify(style.justify, main_space - used, items.len());
+    let main_start = main(direction, Size(content.x, content.y));
+    let cross_start = cross(direction, Size(content.x, content.y));
+    let mut children = Vec::with_capacity(items.len());
+
+    for (((child, child_style), item), size) in
+        node.children.iter().zip(styles).zip(&items).zip(&intrinsic)
+    {
+        let (cross_size, cross_offset) =
+            place_cross(direction, &style, &child_style, *size, cross_space);
+        let main_position = main_start + cursor + item.margin.0;
+        let child_bounds = match direction {
+            Direction::Row => Rect::new(
+                main_position,
+                cross_start + cross_offset,
+                item.size,
+                cross_size,
+            ),
+            Direction::Column => Rect::new(
+                cross_start + cross_offset,
+                main_position,
+                cross_size,
+                item.size,
+            ),
+        };
+
+        children.push(arrange(child, child_style, child_bounds, measure));
+        cursor += item.margin.0 + item.size + item.margin.1 + style.gap + spacing;
+    }
+
+    Layout {
+        rect: bounds,
+        content,
+        style,
+        children,
+    }
+}
+
+/// Draws `node` with its alpha multiplied by `opacity`, the product of its ancestors'.
+fn draw_node(
+    scene: &mut Scene,
+    node: &Node,
+    layout: &Layout,
+    opacity: f32,
+    measure: &impl TextMeasure,
+) {
+    let style = &layout.style;
+    let opacity = opacity * style.opacity;
+
+    if opacity <= 0.0 {
+        return;
+    }
+
+    let background = fade(style.background, opacity);
+    let border = fade(style.border.color, opacity);
+
+    if background.3 > 0.0 || (style.border.width > 0.0 && border.3 > 0.0) {
+        let rect = layout.rect;
+        scene.quad(
+            WidgetInstance::new(
+                rect.x,
+                rect.y,
+                rect.width,
+                rect.height,
+                background,
+                style.radius,
+            )
+            .with_border(style.border.width, border),
+        );
+    }
+
+    // Clip to the padding box, like CSS, so content can run into the padding but never over
+    // the border.
+    let clip = inset(layout.rect, &Edges::uniform(style.border.width));
+    let overflows = |rect: &Rect| {
+        rect.x < clip.x
+            || rect.y < clip.y
+            || rect.right() > clip.right()
+            || rect.bottom() > clip.bottom()
+    };
+
+    if !node.content.is_empty() {
+        let vertical = style.text_orientation.is_vertical();
+        let inline = |size: Size| if vertical { size.1 } else { size.0 };
+        let space = inline(Size(layout.content.width, layout.content.height));
+        let mut text = node.content.clone();
+        let mut size = text_size(&text, style, measure);
+
+        if style.overflow == Overflow::Ellipsis && inline(size) > space {
+            text = ellipsize(&text, style, space, measure);
+            size = text_size(&text, style, measure);
+        }
+
+        let free = space - inline(size);
+        let offset = if free > 0.0 {
+            match style.text_align {
+                TextAlign::Start => 0.0,
+                TextAlign::Center => free / 2.0,
+                TextAlign::End => free,
+            }
+        } else {
+            0.0
+        };
+        let position = if vertical {
+            Position(layout.content.x, layout.content.y + offset)
+        } else {
+            Position(layout.content.x + offset, layout.content.y)
+        };
+        let text_rect = Rect::new(position.0, position.1, size.0, size.1);
+        let clipped = style.overflow != Overflow::Visible && overflows(&text_rect);
+
+        if clipped {
+            scene.push_clip(clip);
+        }
+
+        for mut run in text_runs(&text, style, position, measure) {
+            run.color = fade(run.color, opacity);
+            scene.text(run);
+        }
+
+        if clipped {
+            scene.pop_clip();
+        }
+    }
+
+    let clipped = style.overflow != Overflow::Visible
+        && layout.children.iter().any(|child| overflows(&child.rect));
+
+    if clipped {
+        scene.push_clip(clip);
+    }
+
+    for (child, layout) in node.children.iter().zip(&layout.children) {
+        draw_node(scene, child, layout, opacity, measure);
+    }
+
+    if clipped {
+        scene.pop_clip();
+    }
+}
+
+/// The longest start of `text` that, followed by an ellipsis, fits in `length` along the
+/// inline axis of `style`.
+fn ellipsize(text: &str, style: &ComputedStyle, length: f32, measure: &impl TextMeasure) -> String {
+    let boundaries: Vec<usize> = text.char_indices().map(|(index, _)| index).collect();
+    let truncated = |count: usize| {
+        let end = boundaries.get(count).copied().unwrap_or(text.len());
+        format!("{}{ELLIPSIS}", text[..end].trim_end())
+    };
+    let fits = |candidate: String| {
+        let size = text_size(&candidate, style, measure);
+        let inline = if style.text_orientation.is_vertical() {
+            size.1
+        } else {
+            size.0
+        };
+        inline <= length
+    };
+
+    // Text only gets longer as characters are added, so search for the longest fitting prefix.
+    let (mut low, mut high) = (0, boundaries.len());
+
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+
+        if fits(truncated(middle)) {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+
+    truncated(low)
+}
+
+/// Grows or shrinks `items` so they fill `space` as closely as their limits allow.
+///
+/// Items that hit a limit are frozen at it and the rest of the space is handed out again
+/// among the others, as in CSS.
+fn resolve_flexible_lengths(items: &mut [FlexItem], space: f32) {
+    let outer = |item: &FlexItem| item.base + item.margin.0 + item.margin.1;
+    let growing = items.iter().map(outer).sum::<f32>() < space;
+
+    for item in items.iter_mut() {
+        let factor = if growing { item.grow } else { item.shrink };
+        item.frozen = factor <= 0.0;
+    }
+
+    loop {
+        let weights: Vec<f32> = items
+            .iter()
+            .map(|item| match (item.frozen, growing) {
+                (true, _) => 0.0,
+                (false, true) => item.grow,
+                (false, false) => item.shrink * item.base,
+            })
+            .collect();
+        let total_weight: f32 = weights.iter().sum();
+
+        if total_weight <= 0.0 {
+            return;
+        }
+
+        let mut free = space
+            - items
+                .iter()
+                .map(|item| {
+                    let size = if item.frozen { item.size } else { item.base };
+                    size + item.margin.0 + item.margin.1
+                })
+                .sum::<f32>();
+
+        // Grow factors adding up to less than one only claim that fraction of the space.
+        if growing && total_weight < 1.0 {
+            free *= total_weight;
+        }
+
+        let mut violation = 0.0;
+        let mut targets = vec![0.0; items.len()];
+
+        for ((item, weight), target) in items.iter_mut().zip(&weights).zip(&mut targets) {
+            if item.frozen {
+                continue;
+            }
+
+            *target = item.base + free * weight / total_weight;
+            item.size = clamp(*target, item.min, item.max).max(0.0);
+            violation += item.size - *target;
+        }
+
+        if violation.abs() < 0.01 {
+            return;
+        }
+
+        // Freeze whichever items were clamped in the direction of the overall violation.
+        for (item, target) in items.iter_mut().zip(&targets) {
+            if !item.frozen
+                && ((violation > 0.0 && item.size > *target)
+                    || (violation < 0.0 && item.size < *target))
+            {
+                item.frozen = true;
+            }
+        }
+    }
+}
+
+/// Offset of the first item and extra space after each item for `free` leftover space among
+/// `count` items.
+fn justify(justify: Justify, free: f32, count: usize) -> (f32, f32) {
+    let count = count as f32;
+
+    // Distributing negative space is meaningless, so the spacing modes fall back to start or
+    // center alignment when the items overflow, like CSS.
+    match justify {
+        Justify::Start => (0.0, 0.0),
+        Justify::End => (free, 0.0),
+        Justify::Center => (free / 2.0, 0.0),
+        Justify::SpaceBetween if free > 0.0 && count > 1.0 => (0.0, free / (count - 1.0)),
+        Justify::SpaceBetween => (0.0, 0.0),
+        Justify::SpaceAround if free > 0.0 => (free / count / 2.0, free / count),
+        Justify::SpaceEvenly if free > 0.0 => (free / (count + 1.0), free / (count + 1.0)),
+        Justify::SpaceAround | Justify::SpaceEvenly => (free / 2.0, 0.0),
+    }
+}
+
+/// Size and offset of a child computed to `child` across its parent's main axis, within
+/// `space`.
+fn place_cross(
+    direction: Direction,
+    parent: &ComputedStyle,
+    child: &ComputedStyle,
+    intrinsic: Size,
+    space: f32,
+) -> (f32, f32) {
+    let (margin_start, margin_end, fixed, min, max) = match direction {
+        Direction::Row => (
+            child.margin.top,
+            child.margin.bottom,
+            child.height.px(),
+            child.min_height,
+            child.max_height,
+        ),
+        Direction::Column => (
+            child.margin.left,
+            child.margin.right,
+            child.width.px(),
+            child.min_width,
+            child.max_width,
+        ),
+    };
+    let available = space - margin_start - margin_end;
+    let align = child.align_self.unwrap_or(parent.align);
+
+    if align == Align::Stretch && fixed.is_none() {
+        return (clamp(available, min, max).max(0.0), margin_start);
+    }
+
+    let size = cross(direction, intrinsic);
+    let offset = match align {
+        Align::Start | Align::Stretch => 0.0,
+        Align::End => available - size,
+        Align::Center => (available - size) / 2.0,
+    };
+
+    (size, margin_start + offset)
+}
+
+fn main_limits(direction: Direction, style: &ComputedStyle) -> (f32, f32) {
+    match direction {
+        Direction::Row => (style.min_width, style.max_width),
+        Direction::Column => (style.min_height, style.max_height),
+    }
+}
+
+fn main_margins(direction: Direction, margin: &Edges) -> (f32, f32) {
+    match direction {
+        Direction::Row => (margin.left, margin.right),
+        Direction::Column => (margin.top, margin.bottom),
+    }
+}
+
+fn main(direction: Direction, size: Size) -> f32 {
+    match direction {
+        Direction::Row => size.0,
+        Direction::Column => size.1,
+    }
+}
+
+fn cross(direction: Direction, size: Size) -> f32 {
+    match direction {
+        Direction::Row => size.1,
+        Direction::Column => size.0,
+    }
+}
+
+fn inset(rect: Rect, edges: &Edges) -> Rect {
+    Rect::new(
+        rect.x + edges.left,
+        rect.y + edges.top,
+        (rect.width - edges.horizontal()).max(0.0),
+        (rect.height - edges.vertical()).max(0.0),
+    )
+}
+
+/// Keeps `value` within `min` and `max`, with `min` winning if they conflict, as in CSS.
+fn clamp(value: f32, min: f32, max: f32) -> f32 {
+    value.min(max).max(min)
+}
+
+fn fade(color: Color, opacity: f32) -> Color {
+    Color(color.0, color.1, color.2, color.3 * opacity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAR_WIDTH: f32 = 8.0;
+    const LINE_HEIGHT: f32 = 16.0;
+
+    /// Measures every character as [`CHAR_WIDTH`] wide, so results don't depend on fonts.
+    struct FixedWidth;
+
+    impl TextMeasure for FixedWidth {
+        fn measure_text(&self, run: &TextRun) -> Size {
+            Size(run.text.chars().count() as f32 * CHAR_WIDTH, LINE_HEIGHT)
+        }
+    }
+
+    fn item(base: f32, grow: f32, shrink: f32, min: f32, max: f32) -> FlexItem {
+        FlexItem {
+            base,
+            size: base,
+            min,
+            max,
+            grow,
+            shrink,
+            margin: (0.0, 0.0),
+            frozen: false,
+        }
+    }
+
+    fn sizes(items: &[FlexItem]) -> Vec<f32> {
+        items.iter().map(|item| item.size).collect()
+    }
+
+    #[test]
+    fn growing_freezes_items_at_their_max() {
+        let mut items = [
+            item(10.0, 1.0, 1.0, 0.0, 20.0),
+            item(10.0, 1.0, 1.0, 0.0, f32::INFINITY),
+        ];
+        resolve_flexible_lengths(&mut items, 100.0);
+
+        assert_eq!(sizes(&items), [20.0, 80.0]);
+    }
+
+    #[test]
+    fn shrinking_freezes_items_at_their_min() {
+        let mut items = [
+            item(50.0, 0.0, 1.0, 40.0, f32::INFINITY),
+            item(50.0, 0.0, 1.0, 0.0, f32::INFINITY),
+        ];
+        resolve_flexible_lengths(&mut items, 60.0);
+
+        assert_eq!(sizes(&items), [40.0, 20.0]);
+    }
+
+    #[test]
+    fn shrinking_is_weighted_by_size() {
+        let mut items = [
+            item(30.0, 0.0, 1.0, 0.0, f32::INFINITY),
+            item(90.0, 0.0, 1.0, 0.0, f32::INFINITY),
+        ];
+        resolve_flexible_lengths(&mut items, 80.0);
+
+        assert_eq!(sizes(&items), [20.0, 60.0]);
+    }
+
+    #[test]
+    fn grow_factors_below_one_claim_part_of_the_space() {
+        let mut items = [item(0.0, 0.5, 1.0, 0.0, f32::INFINITY)];
+        resolve_flexible_lengths(&mut items, 100.0);
+
+        assert_eq!(sizes(&items), [50.0]);
+    }
+
+    #[test]
+    fn justify_spaces_positive_free_space() {
+        assert_eq!(justify(Justify::SpaceBetween, 20.0, 3), (0.0, 10.0));
+        assert_eq!(justify(Justify::SpaceAround, 30.0, 3), (5.0, 10.0));
+        assert_eq!(justify(Justify::SpaceEvenly, 40.0, 3), (10.0, 10.0));
+    }
+
+    #[test]
+    fn justify_falls_back_on_negative_free_space() {
+        assert_eq!(justify(Justify::SpaceBetween, -20.0, 3), (0.0, 0.0));
+        assert_eq!(justify(Justify::SpaceAround, -20.0, 3), (-10.0, 0.0));
+        assert_eq!(justify(Justify::SpaceEvenly, -20.0, 3), (-10.0, 0.0));
+        assert_eq!(justify(Justify::End, -20.0, 3), (-20.0, 0.0));
+        assert_eq!(justify(Justify::Center, -20.0, 3), (-10.0, 0.0));
+    }
+
+    #[test]
+    fn place_cross_stretches_within_margins() {
+        let parent = ComputedStyle::default();
+        let child = ComputedStyle {
+            margin: Edges {
+                top: 2.0,
+                bottom: 4.0,
+                ..Edges::ZERO
+            },
+            ..ComputedStyle::default()
+        };
+
+        assert_eq!(
+            place_cross(Direction::Row, &parent, &child, Size(10.0, 10.0), 30.0),
+            (24.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn place_cross_aligns_by_the_child_over_the_parent() {
+        let parent = ComputedStyle {
+            align: Align::Center,
+            ..ComputedStyle::default()
+        };
+        let child = ComputedStyle::default();
+        let end = ComputedStyle {
+            align_self: Some(Align::End),
+            ..ComputedStyle::default()
+        };
+        let intrinsic = Size(10.0, 10.0);
+
+        assert_eq!(
+            place_cross(Direction::Row, &parent, &child, intrinsic, 30.0),
+            (10.0, 10.0)
+        );
+        assert_eq!(
+            place_cross(Direction::Row, &parent, &end, intrinsic, 30.0),
+            (10.0, 20.0)
+        );
+        assert_eq!(
+            place_cross(Direction::Column, &parent, &end, Size(6.0, 10.0), 30.0),
+            (6.0, 24.0)
+        );
+    }
+
+    #[test]
+    fn place_cross_keeps_fixed_sizes_from_stretching() {
+        let parent = ComputedStyle::default();
+        let child = ComputedStyle {
+            height: Length::Px(12.0),
+            ..ComputedStyle::default()
+        };
+
+        assert_eq!(
+            place_cross(Direction::Row, &parent, &child, Size(10.0, 12.0), 30.0),
+            (12.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn ellipsize_keeps_the_longest_fitting_start() {
+        let style = ComputedStyle::default();
+
+        assert_eq!(ellipsize("hello world", &style, 40.0, &FixedWidth), "hell…");
+        // Spaces before the ellipsis are dropped.
+        assert_eq!(ellipsize("ab cd", &style, 32.0, &FixedWidth), "ab…");
+    }
+
+    #[test]
+    fn ellipsize_to_zero_width_leaves_the_ellipsis() {
+        let style = ComputedStyle::default();
+
+        assert_eq!(ellipsize("hello", &style, 0.0, &FixedWidth), "…");
+        assert_eq!(ellipsize("", &style, 0.0, &FixedWidth), "…");
+    }
+}
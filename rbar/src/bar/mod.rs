@@ -1,45 +1,927 @@
+pub mod layout;
 pub mod module;
 pub mod node;
 pub mod style;
+pub mod stylesheet;
+
+use std::{
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
-use rbar_render::SurfaceRenderer;
-use smithay_client_toolkit::shell::wlr_layer::LayerSurface;
-use wayland_client::protocol::wl_output::WlOutput;
+use rbar_render::{Color, Position, Rect, Scene, TextMeasure, TextRun};
+use smithay_client_toolkit::shell::{
+    WaylandSurface,
+    wlr_layer::{KeyboardInteractivity, LayerSurface},
+};
+use wayland_client::{QueueHandle, protocol::wl_output::WlOutput};
+
+use crate::{
+    animation::{Animated, Easing, Transition},
+    app::App,
+    backend::BarSurface,
+    backoff::Backoff,
+    bar::{
+        module::Module,
+        node::Node,
+        style::{ComputedStyle, Style},
+        stylesheet::Stylesheet,
+    },
+    config::{BarConfig, Edge, Layer, ModuleConfig, ModulesConfig, OnFullscreen},
+    scale::SurfaceScale,
+};
+
+const TRANSPARENT: Color = Color(0.0, 0.0, 0.0, 0.0);
+/// Kind of the module a bar shows while the configuration fails to load.
+const CONFIG_ERROR_KIND: &str = "config-error";
+const SLIDE_TRANSITION: Transition = Transition::new(Duration::from_millis(200), Easing::EASE_OUT);
 
-use crate::bar::module::Module;
+/// One of the three groups of modules in a bar.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Section {
+    Left,
+    /// Stays centered on the bar, however wide the other two are.
+    Center,
+    Right,
+}
+
+/// A bar's modules by section.
+#[derive(Default)]
+pub struct Sections {
+    pub left: Vec<Module>,
+    pub center: Vec<Module>,
+    pub right: Vec<Module>,
+}
 
 pub struct Bar {
+    // Fields drop in order, and the drawing surface and scale are made from the layer
+    // surface's `wl_surface`, so they go first.
+    surface: BarSurface,
+    scale: SurfaceScale,
     pub layer_surface: LayerSurface,
     pub output: WlOutput,
+    /// Size in logical pixels, as configured by the compositor.
     pub width: u32,
     pub height: u32,
-    pub modules: Vec<Module>,
-    surface_renderer: SurfaceRenderer,
+    sections: Sections,
+    pub stylesheet: Rc<Stylesheet>,
+    /// When to try drawing, or recreating the surfaces of a closed bar, again.
+    pub backoff: Backoff,
+    /// Set once the compositor closed the layer surface, until new surfaces are attached.
+    closed: bool,
+    config: BarConfig,
+    /// Thickness the bar has when shown, in logical pixels.
+    thickness: u32,
+    /// The last [`content_thickness`](Self::content_thickness), until modules, styles or the
+    /// scale change.
+    content_thickness: Option<u32>,
+    /// How far the bar has slid in, from 0.0 while hidden to 1.0 while shown.
+    reveal: Animated<f32>,
+    /// How far the bar had slid in when it was last drawn.
+    drawn_reveal: f32,
+    /// Set while auto-hide has shrunk the surface to its hot zone.
+    collapsed: bool,
+    /// When auto-hide hides the bar next.
+    hide_at: Option<Instant>,
+    /// Set while the pointer is over the bar.
+    pointer_inside: bool,
+    /// Set while the focused window is fullscreen on the bar's output.
+    covered: bool,
+    /// Size of the buffer last rendered, in physical pixels.
+    buffer_size: (u32, u32),
+    scene: Scene,
+    /// Where each module was drawn in the last frame.
+    module_rects: Vec<Rect>,
+    /// Set when the whole bar has to be redrawn.
+    dirty: bool,
+    /// Set between committing a frame and its frame callback.
+    frame_pending: bool,
 }
 
 impl Bar {
     pub fn new(
         layer_surface: LayerSurface,
         output: WlOutput,
-        surface_renderer: SurfaceRenderer,
+        surface: BarSurface,
+        scale: SurfaceScale,
+        stylesheet: Rc<Stylesheet>,
+        config: BarConfig,
     ) -> Result<Self> {
-        Ok(Self {
+        let mut bar = Self {
+            surface,
+            scale,
             layer_surface,
             output,
             width: 0,
             height: 0,
-            modules: vec![],
-            surface_renderer,
-        })
+            sections: Sections::new(&config.modules),
+            stylesheet,
+            backoff: Backoff::default(),
+            closed: false,
+            config,
+            thickness: 0,
+            content_thickness: None,
+            reveal: Animated::new(1.0, SLIDE_TRANSITION),
+            drawn_reveal: 1.0,
+            collapsed: false,
+            hide_at: None,
+            pointer_inside: false,
+            covered: false,
+            buffer_size: (0, 0),
+            scene: Scene::new(),
+            module_rects: vec![],
+            dirty: true,
+            frame_pending: false,
+        };
+        bar.reset_auto_hide(Instant::now());
+        bar.place();
+        bar.layer_surface.commit();
+
+        Ok(bar)
+    }
+
+    pub fn config(&self) -> &BarConfig {
+        &self.config
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Stops drawing after the compositor closed the layer surface. Modules keep their state
+    /// for when the bar gets new surfaces from [`reattach`](Self::reattach).
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    /// Moves a closed bar onto freshly created surfaces and asks the compositor to place it
+    /// again. Drawing resumes with the first configure.
+    pub fn reattach(
+        &mut self,
+        layer_surface: LayerSurface,
+        surface: BarSurface,
+        scale: SurfaceScale,
+    ) {
+        // The old drawing surface and scale refer to the old `wl_surface`, which goes with the
+        // old layer surface.
+        self.surface = surface;
+        self.scale = scale;
+        self.layer_surface = layer_surface;
+        self.closed = false;
+        self.width = 0;
+        self.height = 0;
+        self.buffer_size = (0, 0);
+        self.module_rects.clear();
+        self.frame_pending = false;
+        self.invalidate();
+        self.place();
+        self.layer_surface.commit();
+    }
+
+    /// Asks the compositor to put the bar where its configuration says, sized to fit the
+    /// content unless it has a fixed thickness, or down to the hot zone while auto-hide has
+    /// collapsed it. Takes effect on the next commit.
+    fn place(&mut self) {
+        self.thickness = match self.config.thickness {
+            Some(thickness) => thickness,
+            None => self.content_thickness(),
+        };
+        let config = &self.config;
+        let (width, height) = if self.collapsed {
+            config.size(config.auto_hide.hot_zone)
+        } else {
+            config.size(self.thickness)
+        };
+        // A collapsed bar leaves the space to windows, like a bar that was never there.
+        let exclusive_zone = if self.collapsed {
+            0
+        } else {
+            config.exclusive_zone(self.thickness)
+        };
+        let margin = config.margin;
+
+        self.layer_surface.set_layer(self.layer().into());
+        self.layer_surface.set_anchor(config.position.anchor());
+        self.layer_surface
+            .set_margin(margin.top, margin.right, margin.bottom, margin.left);
+        self.layer_surface
+            .set_keyboard_interactivity(KeyboardInteractivity::None);
+        self.layer_surface.set_size(width, height);
+        self.layer_surface.set_exclusive_zone(exclusive_zone);
+    }
+
+    /// The layer the bar is on, which is the bottom one while lowered under a fullscreen
+    /// window.
+    fn layer(&self) -> Layer {
+        match self.config.layer {
+            Layer::Top | Layer::Overlay
+                if self.covered && self.config.on_fullscreen == OnFullscreen::Lower =>
+            {
+                Layer::Bottom
+            }
+            layer => layer,
+        }
+    }
+
+    /// Whether the bar is hidden for a fullscreen window.
+    fn is_hidden(&self) -> bool {
+        self.covered && self.config.on_fullscreen == OnFullscreen::Hide
+    }
+
+    /// Records whether a fullscreen window is on the bar's output, hiding or lowering the bar
+    /// as configured.
+    pub fn set_covered(&mut self, qh: &QueueHandle<App>, covered: bool) -> Result<()> {
+        if covered == self.covered {
+            return Ok(());
+        }
+
+        let was_hidden = self.is_hidden();
+        self.covered = covered;
+        self.update_visibility(was_hidden);
+        self.redraw(qh)
+    }
+
+    /// Unmaps the bar if it just got hidden, or places it again otherwise, which also maps a
+    /// bar that was hidden once the compositor configures it.
+    fn update_visibility(&mut self, was_hidden: bool) {
+        if self.closed {
+            return;
+        }
+
+        if !self.is_hidden() {
+            self.place();
+            self.layer_surface.commit();
+        } else if !was_hidden {
+            // Committing without a buffer unmaps a layer surface, after which it waits for
+            // another initial commit and configure to be mapped again.
+            let surface = self.layer_surface.wl_surface();
+            surface.attach(None, 0, 0);
+            surface.commit();
+            self.width = 0;
+            self.height = 0;
+            self.buffer_size = (0, 0);
+            self.frame_pending = false;
+        }
+    }
+
+    /// Shows the bar fully after a configuration change, hiding it after the auto-hide delay
+    /// if that is enabled.
+    fn reset_auto_hide(&mut self, now: Instant) {
+        let auto_hide = self.config.auto_hide;
+        self.collapsed = false;
+        self.reveal.set(1.0);
+        self.hide_at = auto_hide.enabled.then(|| now + auto_hide.delay);
+    }
+
+    /// Slides an auto-hiding bar in, to hide again after the delay unless the pointer is on
+    /// it by then. Takes effect on the next redraw.
+    pub fn reveal(&mut self, now: Instant) {
+        let auto_hide = self.config.auto_hide;
+
+        if !auto_hide.enabled {
+            return;
+        }
+
+        if self.collapsed {
+            self.collapsed = false;
+            self.place();
+            self.layer_surface.commit();
+        }
+
+        self.reveal.animate_to(1.0, now);
+        self.hide_at = (!self.pointer_inside).then(|| now + auto_hide.delay);
+    }
+
+    /// Slides an auto-hiding bar out. Once it is out of sight the surface shrinks to the hot
+    /// zone. Takes effect on the next redraw.
+    pub fn hide(&mut self, now: Instant) {
+        if self.config.auto_hide.enabled {
+            self.hide_at = None;
+            self.reveal.animate_to(0.0, now);
+        }
+    }
+
+    /// When [`tick`](Self::tick) has something to do next, not counting retries.
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        self.hide_at
     }
 
-    pub fn configure(&mut self, width: u32, height: u32) -> Result<()> {
+    /// Hides the bar if the auto-hide delay ran out.
+    pub fn tick(&mut self, qh: &QueueHandle<App>, now: Instant) -> Result<()> {
+        if self.hide_at.is_some_and(|hide_at| hide_at <= now) {
+            self.hide(now);
+            return self.redraw(qh);
+        }
+
+        Ok(())
+    }
+
+    /// Thickness the modules need across the bar, at least the height of a line of text.
+    fn content_thickness(&mut self) -> u32 {
+        if let Some(thickness) = self.content_thickness {
+            return thickness;
+        }
+
+        let root = module_tree(&self.sections, &self.stylesheet, self.config.position);
+        let style = root.style.compute(&ComputedStyle::default());
+        let size = layout::intrinsic_size(&root, &style, &self.surface);
+        let line = TextRun {
+            rotated: false,
+            ..layout::text_run(" ", &style, Position(0.0, 0.0))
+        };
+        let line_height = self.surface.measure_text(&line).1;
+        let thickness = if self.config.position.is_vertical() {
+            size.0
+        } else {
+            size.1
+        };
+
+        let thickness = thickness.max(line_height).ceil() as u32;
+        self.content_thickness = Some(thickness);
+
+        thickness
+    }
+
+    /// Adopts the size from a layer surface configure and draws right away, since the
+    /// compositor waits for a buffer at the new size.
+    pub fn configure(&mut self, qh: &QueueHandle<App>, width: u32, height: u32) -> Result<()> {
         self.width = width;
         self.height = height;
-        self.surface_renderer.render()?;
-        self.surface_renderer.set_size(self.width, self.height);
+        self.invalidate();
+        self.frame_pending = false;
+        self.redraw(qh)
+    }
 
-        Ok(())
+    /// Catches up with a change to the bar's output, such as a new mode, scale or transform,
+    /// which may also have changed which configuration applies to it.
+    ///
+    /// The drawing surface is reconfigured and everything laid out again even if nothing the
+    /// bar knows of changed, since a mode switch can leave the old surface stale.
+    pub fn output_changed(
+        &mut self,
+        qh: &QueueHandle<App>,
+        config: BarConfig,
+        scale: i32,
+    ) -> Result<()> {
+        // The output may match other rules now, which may give it other modules.
+        if config.modules != self.config.modules {
+            self.set_modules(&config.modules);
+        }
+
+        // A closed bar is placed with whatever configuration applies when it is reattached.
+        if self.closed {
+            self.config = config;
+            return Ok(());
+        }
+
+        if config != self.config {
+            let was_hidden = self.is_hidden();
+            self.config = config;
+            self.reset_auto_hide(Instant::now());
+            self.update_visibility(was_hidden);
+        }
+
+        self.scale.set_integer(scale);
+        self.buffer_size = (0, 0);
+        self.content_thickness = None;
+        self.invalidate();
+        self.redraw(qh)
+    }
+
+    /// Adopts a reloaded configuration: `config` for the bar, with its modules, and
+    /// `stylesheet`. Modules that are still configured are kept, and any configuration error
+    /// shown is removed.
+    pub fn reload(
+        &mut self,
+        qh: &QueueHandle<App>,
+        config: BarConfig,
+        stylesheet: Rc<Stylesheet>,
+    ) -> Result<()> {
+        self.stylesheet = stylesheet;
+        self.sections
+            .reconfigure(&self.config.modules, &config.modules);
+        self.module_rects.clear();
+        self.content_thickness = None;
+
+        if self.closed {
+            self.config = config;
+            return Ok(());
+        }
+
+        let was_hidden = self.is_hidden();
+
+        if config != self.config {
+            self.config = config;
+            self.reset_auto_hide(Instant::now());
+        }
+
+        // Placing the bar again also fits it to the new modules and styles.
+        self.update_visibility(was_hidden);
+        self.invalidate();
+        self.redraw(qh)
+    }
+
+    /// Replaces the modules with the ones `modules` lists, keeping those the bar's
+    /// configuration still lists and any configuration error shown.
+    fn set_modules(&mut self, modules: &ModulesConfig) {
+        let failed = self.sections.shows_config_error();
+        self.sections.reconfigure(&self.config.modules, modules);
+        self.sections.set_config_error(failed);
+        self.module_rects.clear();
+        self.content_thickness = None;
+    }
+
+    /// Shows or removes the module telling that the configuration failed to load, at the
+    /// end of the right section.
+    pub fn set_config_error(&mut self, qh: &QueueHandle<App>, failed: bool) -> Result<()> {
+        if !self.sections.set_config_error(failed) {
+            return Ok(());
+        }
+
+        self.module_rects.clear();
+        self.content_thickness = None;
+
+        if self.closed {
+            return Ok(());
+        }
+
+        self.update_visibility(self.is_hidden());
+        self.invalidate();
+        self.redraw(qh)
+    }
+
+    /// Draws the whole bar again after drawing failed, starting over with a freshly configured
+    /// surface and without waiting for the frame callback of the failed frame.
+    pub fn retry(&mut self, qh: &QueueHandle<App>) -> Result<()> {
+        self.frame_pending = false;
+        self.buffer_size = (0, 0);
+        self.invalidate();
+        self.redraw(qh)
+    }
+
+    /// Applies the integer scale of the outputs the bar is on.
+    pub fn set_integer_scale(&mut self, qh: &QueueHandle<App>, scale: i32) -> Result<()> {
+        if self.scale.set_integer(scale) {
+            self.content_thickness = None;
+            self.invalidate();
+        }
+
+        self.redraw(qh)
+    }
+
+    /// Applies the compositor's preferred fractional scale.
+    pub fn set_fractional_scale(&mut self, qh: &QueueHandle<App>, scale: f64) -> Result<()> {
+        if self.scale.set_fractional(scale) {
+            self.content_thickness = None;
+            self.invalidate();
+        }
+
+        self.redraw(qh)
+    }
+
+    /// Marks the whole bar for redrawing, for changes that modules don't track themselves.
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Updates hover states for the pointer at `position` in surface coordinates, or `None`
+    /// when it left the bar. An auto-hiding bar comes out while the pointer is on it.
+    pub fn pointer_moved(&mut self, position: Option<(f64, f64)>, now: Instant) {
+        self.pointer_inside = position.is_some();
+
+        if self.pointer_inside {
+            self.reveal(now);
+        } else if self.config.auto_hide.enabled && !self.collapsed {
+            self.hide_at = Some(now + self.config.auto_hide.delay);
+        }
+
+        for (module, rect) in self.sections.modules_mut().zip(&self.module_rects) {
+            let hovered = position.is_some_and(|(x, y)| rect.contains(x as f32, y as f32));
+            module.set_hovered(hovered);
+        }
+    }
+
+    /// Presses or releases the module under the pointer, for `:active` styles.
+    pub fn pointer_pressed(&mut self, pressed: bool) {
+        for module in self.sections.modules_mut() {
+            module.set_active(pressed && module.is_hovered());
+        }
+    }
+
+    /// Handles the frame callback for the last frame: the compositor is ready for another.
+    ///
+    /// Running animations keep their modules dirty, so this keeps drawing one frame per
+    /// callback until they finish.
+    pub fn frame_done(&mut self, qh: &QueueHandle<App>) -> Result<()> {
+        self.frame_pending = false;
+        self.redraw(qh)
+    }
+
+    /// Draws a new frame if anything changed.
+    ///
+    /// While a frame callback is pending this only leaves the dirty flags set, so any number of
+    /// changes within one refresh cycle are coalesced into the frame drawn from
+    /// [`frame_done`](Self::frame_done).
+    pub fn redraw(&mut self, qh: &QueueHandle<App>) -> Result<()> {
+        let now = Instant::now();
+        let modules_dirty = self
+            .sections
+            .modules()
+            .any(|module| module.needs_redraw(now));
+
+        if self.closed
+            || self.is_hidden()
+            || self.frame_pending
+            || self.width == 0
+            || self.height == 0
+        {
+            return Ok(());
+        }
+
+        let reveal = self.reveal.value(now);
+
+        // Out of sight, so give the space back and leave only the hot zone.
+        if !self.collapsed && reveal <= 0.0 && !self.reveal.is_animating(now) {
+            self.collapsed = true;
+            self.place();
+            self.layer_surface.commit();
+        }
+
+        // Sliding moves everything, backgrounds included.
+        if reveal != self.drawn_reveal {
+            self.drawn_reveal = reveal;
+            self.dirty = true;
+        }
+
+        if !self.dirty && (!modules_dirty || self.collapsed) {
+            return Ok(());
+        }
+
+        // Restyled modules may need a thicker or thinner bar, and so may new text on vertical
+        // bars, where upright text is as wide as its widest character.
+        let vertical = self.config.position.is_vertical();
+
+        if self
+            .sections
+            .modules()
+            .any(|module| module.is_restyled() || (vertical && module.is_dirty()))
+        {
+            self.content_thickness = None;
+        }
+
+        if self.config.thickness.is_none() && self.content_thickness() != self.thickness {
+            self.place();
+            self.layer_surface.commit();
+        }
+
+        // Render at physical resolution and map the buffer back onto the logical size.
+        let buffer_size = self.scale.buffer_size(self.width, self.height);
+
+        if buffer_size != self.buffer_size {
+            self.buffer_size = buffer_size;
+            self.surface.set_size(buffer_size.0, buffer_size.1);
+            self.dirty = true;
+        }
+
+        self.surface.set_scale(self.scale.factor() as f32);
+        self.scene.clear();
+
+        // The hot zone of a collapsed bar is drawn empty, only catching the pointer.
+        let bounds = self.slide_bounds(reveal);
+        let module_rects = if self.collapsed {
+            vec![]
+        } else {
+            draw_modules(
+                &mut self.scene,
+                &mut self.sections,
+                &self.stylesheet,
+                self.config.position,
+                bounds,
+                &self.surface,
+                now,
+            )
+        };
+        let damage = self.damage(&module_rects, now);
+        self.module_rects = module_rects;
+        self.dirty = false;
+
+        for module in self.sections.modules_mut() {
+            module.mark_clean();
+        }
+
+        if damage.is_empty() {
+            return Ok(());
+        }
+
+        let surface = self.layer_surface.wl_surface();
+        self.scale.apply(surface, self.width, self.height);
+        surface.frame(qh, surface.clone());
+        self.frame_pending = true;
+        self.surface.render(&self.scene, &damage)
+    }
+
+    /// Where the bar is drawn when it has slid `reveal` of the way in from its edge.
+    fn slide_bounds(&self, reveal: f32) -> Rect {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let hidden = (1.0 - reveal)
+            * if self.config.position.is_vertical() {
+                width
+            } else {
+                height
+            };
+
+        match self.config.position {
+            Edge::Top => Rect::new(0.0, -hidden, width, height),
+            Edge::Bottom => Rect::new(0.0, hidden, width, height),
+            Edge::Left => Rect::new(-hidden, 0.0, width, height),
+            Edge::Right => Rect::new(hidden, 0.0, width, height),
+        }
+    }
+
+    /// Areas, in logical pixels, that differ from the last frame given where each module is
+    /// drawn now.
+    fn damage(&self, module_rects: &[Rect], now: Instant) -> Vec<Rect> {
+        if self.dirty || module_rects.len() != self.module_rects.len() {
+            return vec![Rect::new(0.0, 0.0, self.width as f32, self.height as f32)];
+        }
+
+        let mut damage = Vec::new();
+
+        for ((module, rect), old_rect) in self
+            .sections
+            .modules()
+            .zip(module_rects)
+            .zip(&self.module_rects)
+        {
+            if module.needs_redraw(now) || rect != old_rect {
+                damage.push(*old_rect);
+                damage.push(*rect);
+            }
+        }
+
+        damage
+    }
+}
+
+impl Section {
+    pub const ALL: [Section; 3] = [Section::Left, Section::Center, Section::Right];
+
+    /// Class of the section's node in stylesheets.
+    pub fn class(self) -> &'static str {
+        match self {
+            Section::Left => "left",
+            Section::Center => "center",
+            Section::Right => "right",
+        }
+    }
+}
+
+impl Sections {
+    /// The modules `config` lists, freshly created.
+    pub fn new(config: &ModulesConfig) -> Self {
+        Self {
+            left: config.left.iter().map(Module::from_config).collect(),
+            center: config.center.iter().map(Module::from_config).collect(),
+            right: config.right.iter().map(Module::from_config).collect(),
+        }
+    }
+
+    /// Replaces the modules with the ones `new` lists. Modules created from `old` that `new`
+    /// still lists with the same kind and id are kept rather than created again, so they
+    /// keep their state, even if they moved to another section.
+    pub fn reconfigure(&mut self, old: &ModulesConfig, new: &ModulesConfig) {
+        let current = std::mem::take(self);
+        let mut previous: Vec<(&ModuleConfig, Module)> = [
+            (&old.left, current.left),
+            (&old.center, current.center),
+            (&old.right, current.right),
+        ]
+        .into_iter()
+        .flat_map(|(configs, modules)| configs.iter().zip(modules))
+        .collect();
+        let mut modules = |configs: &[ModuleConfig]| -> Vec<Module> {
+            configs
+                .iter()
+                .map(|config| {
+                    let index = previous
+                        .iter()
+                        .position(|(old, _)| old.kind == config.kind && old.id == config.id);
+
+                    match index {
+                        Some(index) => {
+                            let (old, mut module) = previous.remove(index);
+                            module.reconfigure(old, config);
+                            module
+                        }
+                        None => Module::from_config(config),
+                    }
+                })
+                .collect()
+        };
+
+        self.left = modules(&new.left);
+        self.center = modules(&new.center);
+        self.right = modules(&new.right);
+    }
+
+    pub fn get(&self, section: Section) -> &[Module] {
+        match section {
+            Section::Left => &self.left,
+            Section::Center => &self.center,
+            Section::Right => &self.right,
+        }
+    }
+
+    pub fn get_mut(&mut self, section: Section) -> &mut [Module] {
+        match section {
+            Section::Left => &mut self.left,
+            Section::Center => &mut self.center,
+            Section::Right => &mut self.right,
+        }
+    }
+
+    fn shows_config_error(&self) -> bool {
+        self.right
+            .last()
+            .is_some_and(|module| module.kind == CONFIG_ERROR_KIND)
+    }
+
+    /// Shows or removes the module telling that the configuration failed to load, at the
+    /// end of the right section. Returns whether that changed anything.
+    pub fn set_config_error(&mut self, failed: bool) -> bool {
+        if failed == self.shows_config_error() {
+            return false;
+        }
+
+        if failed {
+            self.right.push(config_error_module());
+        } else {
+            self.right.pop();
+        }
+
+        true
+    }
+
+    /// Every module, from left to right.
+    pub fn modules(&self) -> impl Iterator<Item = &Module> {
+        self.left.iter().chain(&self.center).chain(&self.right)
+    }
+
+    pub fn modules_mut(&mut self) -> impl Iterator<Item = &mut Module> {
+        self.left
+            .iter_mut()
+            .chain(&mut self.center)
+            .chain(&mut self.right)
+    }
+}
+
+/// Lays `sections` out in a bar covering `bounds` along `edge`, styled by `stylesheet`, and
+/// draws them into `scene` with module backgrounds as they are at `now`.
+///
+/// See the [stylesheet documentation](stylesheet) for the node tree this builds.
+///
+/// Returns the area each module covers, in section order, including its padding so glyphs
+/// that overhang their advance are still inside it. Hit-testing these works the same across
+/// and down the bar.
+pub fn draw_modules(
+    scene: &mut Scene,
+    sections: &mut Sections,
+    stylesheet: &Stylesheet,
+    edge: Edge,
+    bounds: Rect,
+    measure: &impl TextMeasure,
+    now: Instant,
+) -> Vec<Rect> {
+    let mut root = module_tree(sections, stylesheet, edge);
+
+    // Modules animate changes to the background the stylesheet gives them, like on `:hover`.
+    for (section, node) in Section::ALL.into_iter().zip(&mut root.children) {
+        for (module, node) in sections.get_mut(section).iter_mut().zip(&mut node.children) {
+            let target = node.style.background.unwrap_or(TRANSPARENT);
+            let transition = node.style.transition.unwrap_or(Transition::NONE);
+            node.style.background = Some(module.background(target, transition, now));
+        }
+    }
+
+    let layout = layout::layout(&root, bounds, measure);
+    layout::draw(scene, &root, &layout, measure);
+
+    // Modules squeezed out of a side section only cover what is left of them.
+    layout
+        .children
+        .iter()
+        .flat_map(|section| {
+            section
+                .children
+                .iter()
+                .map(|module| module.rect.intersect(&section.rect))
+        })
+        .collect()
+}
+
+/// The styled node tree of a bar along `edge` holding `sections`.
+fn module_tree(sections: &Sections, stylesheet: &Stylesheet, edge: Edge) -> Node {
+    let orientation = if edge.is_vertical() {
+        "vertical"
+    } else {
+        "horizontal"
+    };
+    let mut root = Node {
+        classes: vec![edge_class(edge).to_string(), orientation.to_string()],
+        ..Node::container(
+            "bar",
+            Style::default(),
+            Section::ALL
+                .into_iter()
+                .map(|section| Node {
+                    classes: vec![section.class().to_string()],
+                    ..Node::container(
+                        "section",
+                        Style::default(),
+                        sections.get(section).iter().map(module_node).collect(),
+                    )
+                })
+                .collect(),
+        )
+    };
+    stylesheet.apply(&mut root);
+
+    root
+}
+
+fn module_node(module: &Module) -> Node {
+    Node {
+        id: module.id.clone(),
+        classes: ["module".to_string()]
+            .into_iter()
+            .chain(module.classes().iter().cloned())
+            .collect(),
+        hovered: module.is_hovered(),
+        active: module.is_active(),
+        ..Node::container(
+            module.kind.clone(),
+            module.style.clone(),
+            vec![Node::text("label", module.text(), Style::default())],
+        )
+    }
+}
+
+/// The module telling that the configuration failed to load.
+fn config_error_module() -> Module {
+    Module::new(CONFIG_ERROR_KIND, "config error", Style::default())
+}
+
+/// Class of a bar attached to `edge` in stylesheets.
+fn edge_class(edge: Edge) -> &'static str {
+    match edge {
+        Edge::Top => "edge-top",
+        Edge::Bottom => "edge-bottom",
+        Edge::Left => "edge-left",
+        Edge::Right => "edge-right",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn kinds(sections: &Sections) -> Vec<&str> {
+        sections
+            .modules()
+            .map(|module| module.kind.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn failed_reloads_keep_the_modules_and_only_add_the_error() {
+        let config = Config::parse(
+            "[[modules.left]]\nkind = \"clock\"\n[[modules.right]]\nkind = \"battery\"",
+            "test",
+        )
+        .unwrap();
+        let mut sections = Sections::new(&config.modules);
+        sections.left[0].set_text("12:00");
+
+        // A reload that fails to parse keeps `config`, and the bars only show the error.
+        assert!(Config::parse("[[modules.left]]\nkind = ", "test").is_err());
+        assert!(sections.set_config_error(true));
+        assert!(!sections.set_config_error(true));
+        assert_eq!(kinds(&sections), ["clock", "battery", "config-error"]);
+        assert_eq!(sections.left[0].text(), "12:00");
+
+        // The next successful reload drops the error and still keeps the modules' state.
+        let fixed = Config::parse(
+            "[[modules.left]]\nkind = \"clock\"\n[[modules.center]]\nkind = \"title\"\n\
+             [[modules.right]]\nkind = \"battery\"",
+            "test",
+        )
+        .unwrap();
+        sections.reconfigure(&config.modules, &fixed.modules);
+
+        assert_eq!(kinds(&sections), ["clock", "title", "battery"]);
+        assert_eq!(sections.left[0].text(), "12:00");
+        assert!(!sections.set_config_error(false));
     }
 }
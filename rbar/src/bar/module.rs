@@ -1,6 +1,189 @@
-use crate::bar::style::Style;
+use std::time::{Duration, Instant};
+
+use rbar_render::Color;
+
+use crate::{
+    animation::{Animated, Easing, Lerp, Pulse, Transition},
+    bar::style::Style,
+    config::ModuleConfig,
+};
+
+const TRANSPARENT: Color = Color(0.0, 0.0, 0.0, 0.0);
+const URGENT_PULSE_PERIOD: Duration = Duration::from_millis(1200);
+/// Class that makes a module pulse its background to draw attention.
+const URGENT_CLASS: &str = "urgent";
 
 pub struct Module {
-    pub text: String,
+    /// Type of the module, such as `clock`, which stylesheets select it by.
+    pub kind: String,
+    pub id: Option<String>,
+    /// Classes for stylesheets, including state classes like `warning` and `critical`.
+    classes: Vec<String>,
+    text: String,
+    /// Overrides whatever the stylesheet says.
     pub style: Style,
+    /// Set when the module's content changed since the bar last drew it.
+    dirty: bool,
+    /// Set when the module's classes or state changed since the bar last drew it, which may
+    /// restyle it.
+    restyled: bool,
+    hovered: bool,
+    active: bool,
+    /// The background stylesheets give the module, moving between values by the
+    /// `transition` they give it. `None` until first drawn.
+    background: Option<Animated<Color>>,
+    urgent: Pulse,
+}
+
+impl Module {
+    pub fn new(kind: impl Into<String>, text: impl Into<String>, style: Style) -> Self {
+        Self {
+            kind: kind.into(),
+            id: None,
+            classes: vec![],
+            text: text.into(),
+            style,
+            dirty: true,
+            restyled: true,
+            hovered: false,
+            active: false,
+            background: None,
+            urgent: Pulse::new(URGENT_PULSE_PERIOD, Easing::EASE_IN_OUT),
+        }
+    }
+
+    pub fn from_config(config: &ModuleConfig) -> Self {
+        let mut module = Self::new(&config.kind, &config.text, Style::default());
+        module.id = config.id.clone();
+
+        for class in &config.classes {
+            module.set_class(class, true);
+        }
+
+        module
+    }
+
+    /// Applies what changed between the `old` and `new` configuration of the module, keeping
+    /// everything else about its state.
+    pub fn reconfigure(&mut self, old: &ModuleConfig, new: &ModuleConfig) {
+        if new.text != old.text {
+            self.set_text(&new.text);
+        }
+
+        for class in &old.classes {
+            if !new.classes.contains(class) {
+                self.set_class(class, false);
+            }
+        }
+
+        for class in &new.classes {
+            self.set_class(class, true);
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the text, marking the module for redrawing if it actually changed.
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        let text = text.into();
+
+        if text != self.text {
+            self.text = text;
+            self.dirty = true;
+        }
+    }
+
+    pub fn classes(&self) -> &[String] {
+        &self.classes
+    }
+
+    /// Adds or removes a class, marking the module for redrawing if that changed anything.
+    pub fn set_class(&mut self, class: &str, enabled: bool) {
+        let position = self.classes.iter().position(|candidate| candidate == class);
+
+        match (position, enabled) {
+            (None, true) => self.classes.push(class.to_string()),
+            (Some(index), false) => {
+                self.classes.remove(index);
+            }
+            _ => return,
+        }
+
+        self.dirty = true;
+        self.restyled = true;
+    }
+
+    pub fn is_hovered(&self) -> bool {
+        self.hovered
+    }
+
+    /// Marks the module as under the pointer, for `:hover` styles.
+    pub fn set_hovered(&mut self, hovered: bool) {
+        if hovered != self.hovered {
+            self.hovered = hovered;
+            self.dirty = true;
+            self.restyled = true;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Marks the module as pressed, for `:active` styles.
+    pub fn set_active(&mut self, active: bool) {
+        if active != self.active {
+            self.active = active;
+            self.dirty = true;
+            self.restyled = true;
+        }
+    }
+
+    /// Moves the background towards `target`, the one stylesheets give the module now, by
+    /// `transition`, and returns the background to draw at `now`. Modules with the `urgent`
+    /// class pulse it, fading it out and in again.
+    pub fn background(&mut self, target: Color, transition: Transition, now: Instant) -> Color {
+        let background = self
+            .background
+            .get_or_insert_with(|| Animated::new(target, transition));
+        background.set_transition(transition);
+        background.animate_to(target, now);
+        let background = background.value(now);
+
+        if self.classes.iter().any(|class| class == URGENT_CLASS) {
+            self.urgent.start(now);
+        } else {
+            self.urgent.stop();
+        }
+
+        background.lerp(&TRANSPARENT, self.urgent.amount(now))
+    }
+
+    /// Whether the module changed since the bar last drew it, as opposed to only animating.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Whether the module's classes or state changed since the bar last drew it.
+    pub fn is_restyled(&self) -> bool {
+        self.restyled
+    }
+
+    /// Whether the module has to be drawn again, either because it changed or because it is
+    /// animating.
+    pub fn needs_redraw(&self, now: Instant) -> bool {
+        self.dirty
+            || self
+                .background
+                .as_ref()
+                .is_some_and(|background| background.is_animating(now))
+            || self.urgent.is_active()
+    }
+
+    pub fn mark_clean(&mut self) {
+        self.dirty = false;
+        self.restyled = false;
+    }
 }
@@ -1,7 +1,48 @@
 use crate::bar::style::Style;
 
+/// An element of a bar's widget tree. A node either shows `content` as text or arranges its
+/// `children` with flexbox rules, see [`layout`](crate::bar::layout).
+#[derive(Clone, Debug, Default)]
 pub struct Node {
+    /// Type the node is selected by in stylesheets, such as `bar`, `clock` or `label`.
+    pub kind: String,
+    pub id: Option<String>,
+    pub classes: Vec<String>,
+    /// Set while the pointer is over the node, for `:hover`.
+    pub hovered: bool,
+    /// Set while the node is pressed, for `:active`.
+    pub active: bool,
     pub style: Style,
     pub content: String,
     pub children: Vec<Node>,
 }
+
+impl Node {
+    /// A leaf showing `content`.
+    pub fn text(kind: impl Into<String>, content: impl Into<String>, style: Style) -> Self {
+        Self {
+            kind: kind.into(),
+            style,
+            content: content.into(),
+            ..Self::default()
+        }
+    }
+
+    /// A container laying out `children`.
+    pub fn container(kind: impl Into<String>, style: Style, children: Vec<Node>) -> Self {
+        Self {
+            kind: kind.into(),
+            style,
+            children,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        self.classes.iter().any(|candidate| candidate == class)
+    }
+}
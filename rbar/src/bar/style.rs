@@ -1 +1,280 @@
-pub struct Style {}
+//! Styling of [`Node`](crate::bar::node::Node)s.
+//!
+//! A [`Style`] holds the properties declared for one node, any of which may be left unset. Layout
+//! and drawing use a [`ComputedStyle`] instead, where every property has a value. For each
+//! property, [`Style::compute`] takes, in order:
+//!
+//! 1. the value declared on the node itself,
+//! 2. for inherited properties, the parent node's computed value,
+//! 3. the default from [`ComputedStyle::default`].
+//!
+//! The inherited properties are the text ones: `foreground`, `font_family`, `font_size`,
+//! `font_weight`, `text_align` and `text_orientation`. Everything else describes the node's own
+//! box and starts from its default on every node. The root node computes against the defaults
+//! as its parent.
+//!
+//! Several sources of declarations for the same node are combined with [`Style::merge`] before
+//! computing, later ones taking precedence.
+
+use rbar_render::{Color, Font, Radius};
+
+use crate::animation::Transition;
+
+const DEFAULT_FONT_FAMILY: &str = "sans-serif";
+const DEFAULT_FONT_SIZE: f32 = 14.0;
+const DEFAULT_FONT_WEIGHT: u16 = 400;
+
+/// Declares [`Style`] with every property optional, [`ComputedStyle`] with every property
+/// set, and the merging and computing between them, from one list of properties.
+macro_rules! properties {
+    ($(
+        $(#[$doc:meta])*
+        $name:ident: $ty:ty = $default:expr, $inheritance:ident;
+    )*) => {
+        /// Properties declared for a node. Unset properties are inherited or take their
+        /// default, see the [module documentation](self).
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct Style {
+            $($(#[$doc])* pub $name: Option<$ty>,)*
+        }
+
+        /// A node's style with every property resolved.
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct ComputedStyle {
+            $($(#[$doc])* pub $name: $ty,)*
+        }
+
+        impl Default for ComputedStyle {
+            fn default() -> Self {
+                Self {
+                    $($name: $default,)*
+                }
+            }
+        }
+
+        impl Style {
+            /// Overrides properties with those set in `other`.
+            pub fn merge(&mut self, other: &Style) {
+                $(
+                    if let Some(value) = &other.$name {
+                        self.$name = Some(value.clone());
+                    }
+                )*
+            }
+
+            /// Resolves every property for a node whose parent computed to `parent`.
+            pub fn compute(&self, parent: &ComputedStyle) -> ComputedStyle {
+                let defaults = ComputedStyle::default();
+
+                ComputedStyle {
+                    $(
+                        $name: match &self.$name {
+                            Some(value) => value.clone(),
+                            None => properties!(@unset $inheritance, parent.$name, defaults.$name),
+                        },
+                    )*
+                }
+            }
+        }
+    };
+    (@unset inherited, $parent:expr, $default:expr) => { $parent.clone() };
+    (@unset reset, $parent:expr, $default:expr) => { $default };
+}
+
+properties! {
+    /// Fill of the border box.
+    background: Color = Color(0.0, 0.0, 0.0, 0.0), reset;
+    /// How modules move to a new `background`, like when hovered.
+    transition: Transition = Transition::NONE, reset;
+    /// Color of text.
+    foreground: Color = Color(1.0, 1.0, 1.0, 1.0), inherited;
+    font_family: String = DEFAULT_FONT_FAMILY.to_string(), inherited;
+    /// Font size in logical pixels.
+    font_size: f32 = DEFAULT_FONT_SIZE, inherited;
+    /// CSS font weight, from 100 to 900.
+    font_weight: u16 = DEFAULT_FONT_WEIGHT, inherited;
+    /// Placement of text along its lines within its box when the box is longer.
+    text_align: TextAlign = TextAlign::Start, inherited;
+    /// Which way text runs, for vertical bars.
+    text_orientation: TextOrientation = TextOrientation::Horizontal, inherited;
+    /// What happens to content that doesn't fit the node's box.
+    overflow: Overflow = Overflow::Clip, reset;
+    /// Multiplies the alpha of everything the node and its descendants draw.
+    opacity: f32 = 1.0, reset;
+    border: Border = Border::NONE, reset;
+    /// Corner radii of the background and border.
+    radius: Radius = Radius::default(), reset;
+    /// Space between the border and the content.
+    padding: Edges = Edges::ZERO, reset;
+    /// Space kept clear around the border.
+    margin: Edges = Edges::ZERO, reset;
+    /// Size of the border box, sized to the content when `Auto`.
+    width: Length = Length::Auto, reset;
+    height: Length = Length::Auto, reset;
+    min_width: f32 = 0.0, reset;
+    max_width: f32 = f32::INFINITY, reset;
+    min_height: f32 = 0.0, reset;
+    max_height: f32 = f32::INFINITY, reset;
+    /// Axis children are placed along.
+    direction: Direction = Direction::Row, reset;
+    /// Placement of children along the main axis when they don't fill it.
+    justify: Justify = Justify::Start, reset;
+    /// Placement of children across the main axis.
+    align: Align = Align::Stretch, reset;
+    /// Overrides the parent's `align` for this node.
+    align_self: Option<Align> = None, reset;
+    /// Space between neighbouring children along the main axis.
+    gap: f32 = 0.0, reset;
+    /// Size along the parent's main axis before growing or shrinking, the intrinsic size when
+    /// `Auto`.
+    basis: Length = Length::Auto, reset;
+    /// Share of the parent's leftover space this node takes.
+    grow: f32 = 0.0, reset;
+    /// How readily this node gives up space when its parent overflows, relative to its size.
+    shrink: f32 = 1.0, reset;
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Row,
+    Column,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Justify {
+    #[default]
+    Start,
+    End,
+    Center,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    End,
+    Center,
+    #[default]
+    Stretch,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Start,
+    Center,
+    End,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TextOrientation {
+    #[default]
+    Horizontal,
+    /// Turned 90° clockwise, reading top to bottom.
+    Sideways,
+    /// Upright characters stacked top to bottom, one per line.
+    Upright,
+}
+
+impl TextOrientation {
+    /// Whether text runs down the screen rather than across it.
+    pub fn is_vertical(self) -> bool {
+        self != Self::Horizontal
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Content may draw outside the box.
+    Visible,
+    /// Content is cut off at the box's padding edge.
+    #[default]
+    Clip,
+    /// Text that doesn't fit is cut short with an ellipsis, other content is clipped.
+    Ellipsis,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Length {
+    #[default]
+    Auto,
+    /// Logical pixels.
+    Px(f32),
+}
+
+/// A solid border drawn inside the border box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Border {
+    pub width: f32,
+    pub color: Color,
+}
+
+/// Widths of the four sides of a box, in logical pixels.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Edges {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl ComputedStyle {
+    pub fn font(&self) -> Font {
+        Font {
+            family: self.font_family.clone(),
+            weight: self.font_weight,
+            italic: false,
+        }
+    }
+
+    /// Widths of the border and padding together, the space between the border box and the
+    /// content box.
+    pub fn insets(&self) -> Edges {
+        Edges {
+            top: self.border.width + self.padding.top,
+            right: self.border.width + self.padding.right,
+            bottom: self.border.width + self.padding.bottom,
+            left: self.border.width + self.padding.left,
+        }
+    }
+}
+
+impl Length {
+    pub fn px(&self) -> Option<f32> {
+        match *self {
+            Self::Auto => None,
+            Self::Px(px) => Some(px),
+        }
+    }
+}
+
+impl Border {
+    pub const NONE: Self = Self {
+        width: 0.0,
+        color: Color(0.0, 0.0, 0.0, 0.0),
+    };
+}
+
+impl Edges {
+    pub const ZERO: Self = Self::uniform(0.0);
+
+    pub const fn uniform(width: f32) -> Self {
+        Self {
+            top: width,
+            right: width,
+            bottom: width,
+            left: width,
+        }
+    }
+
+    pub fn horizontal(&self) -> f32 {
+        self.left + self.right
+    }
+
+    pub fn vertical(&self) -> f32 {
+        self.top + self.bottom
+    }
+}
@@ -0,0 +1,1299 @@
+//! CSS-like stylesheets that resolve into each node's [`Style`].
+//!
+//! A stylesheet is a list of rules such as
+//!
+//! ```css
+//! /* Every clock inside a bar with the id `main`. */
+//! #main clock.warning:hover {
+//!     background: #ffaa00cc;
+//!     padding: 0 8px;
+//! }
+//! ```
+//!
+//! Bars are `bar` nodes holding three `section` nodes, with the classes `left`, `center` and
+//! `right`. A bar has a class for the edge it is attached to, one of `edge-top`,
+//! `edge-bottom`, `edge-left` and `edge-right`, and either `horizontal` or `vertical`. Each
+//! section holds a node per module, typed by the module's kind and with the `module` class,
+//! which shows the module's text in a `label` node. While a reloaded configuration fails to
+//! load, the right section ends with a `config-error` module.
+//!
+//! Selectors combine a node type (or `*`), `#id`, `.class`, `:hover` and `:active`, and a
+//! space between them matches descendants. Modules carry state classes like `.warning` and
+//! `.critical` alongside their own classes. Several selectors can share a rule, separated by
+//! commas.
+//!
+//! Matching rules apply in order of specificity, as in CSS, and in source order when equally
+//! specific. A node's own style overrides every rule.
+//!
+//! A module's `transition`, such as `150ms ease-out` or `spring(300, 20, 1)`, animates changes
+//! to its background, like on `:hover`. Modules with the `urgent` class pulse their background.
+//!
+//! A stylesheet file can start with `@import "colors.css";` statements, which load the named
+//! stylesheets, relative to the importing one, before its own rules.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Error, Result, anyhow};
+use rbar_render::{Color, Radius};
+
+use crate::{
+    animation::{Easing, Transition},
+    bar::{
+        node::Node,
+        style::{
+            Align, Border, Direction, Edges, Justify, Length, Overflow, Style, TextAlign,
+            TextOrientation,
+        },
+    },
+    config::{self, Config},
+};
+
+/// Rules every stylesheet starts with, so bars look reasonable without one.
+///
+/// The left and right sections start out empty and split the space around the center one
+/// evenly, which keeps the center section centered on the bar whatever the sides hold. Side
+/// modules that don't fit shrink, cutting their labels short. Vertical bars stack everything
+/// top to bottom and turn their text sideways. Modules fade their background in and out, and
+/// configuration errors and urgent modules stand out in red.
+const DEFAULT_STYLESHEET: &str = "
+.left, .right {
+    flex-grow: 1;
+    flex-basis: 0;
+}
+
+.right {
+    justify-content: end;
+}
+
+.module {
+    padding: 0 6px;
+    border-radius: 4px;
+    align-items: center;
+    transition: 150ms ease-out;
+}
+
+.module:hover {
+    background: #ffffff26;
+}
+
+label {
+    overflow: ellipsis;
+}
+
+.vertical, .vertical section, .vertical .module {
+    flex-direction: column;
+}
+
+.vertical {
+    text-orientation: sideways;
+}
+
+.vertical .module {
+    padding: 6px 0;
+}
+
+config-error, .urgent {
+    background: #cc3333e6;
+}
+";
+
+/// A parsed stylesheet, see the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct Stylesheet {
+    rules: Vec<Rule>,
+    /// Files the rules were loaded from, imported ones first.
+    files: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    selector: Selector,
+    declarations: Style,
+}
+
+/// Compound selectors from the outermost ancestor to the node itself.
+#[derive(Clone, Debug)]
+struct Selector {
+    compounds: Vec<Compound>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Compound {
+    /// `None` for `*` or when only other parts are given.
+    kind: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    hover: bool,
+    active: bool,
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    /// Name of the file being parsed, for errors.
+    origin: &'a str,
+    position: usize,
+    /// `@import` statements found so far.
+    imports: Vec<Import>,
+}
+
+struct Import {
+    path: PathBuf,
+    /// Offset of the statement, for errors.
+    position: usize,
+}
+
+impl Stylesheet {
+    /// Parses `source`. Errors point at the offending line and column of `origin`. Only
+    /// stylesheets [loaded](Self::load) from files can import others.
+    pub fn parse(source: &str, origin: &str) -> Result<Self> {
+        let mut parser = Parser::new(source, origin);
+        let stylesheet = parser.stylesheet()?;
+
+        match parser.imports.first() {
+            Some(import) => {
+                Err(parser.error_at(import.position, "`@import` only works in stylesheet files"))
+            }
+            None => Ok(stylesheet),
+        }
+    }
+
+    /// The default rules followed by the stylesheet `config` names, or by the user's
+    /// stylesheet if it names none and they have one.
+    pub fn load_user(config: &Config) -> Result<Self> {
+        let mut stylesheet = Self::default();
+        let path = match &config.stylesheet {
+            Some(path) => Some(path.clone()),
+            None => user_path().filter(|path| path.exists()),
+        };
+
+        if let Some(path) = path {
+            stylesheet.extend(Self::load(&path)?);
+        }
+
+        Ok(stylesheet)
+    }
+
+    /// Loads the stylesheet at `path`, after the stylesheets it imports.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::load_imported(path, &mut vec![])
+    }
+
+    /// Loads the stylesheet at `path` as imported by `importers`, the files being loaded that
+    /// lead to it, outermost first.
+    fn load_imported(path: &Path, importers: &mut Vec<PathBuf>) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let origin = path.display().to_string();
+        let mut parser = Parser::new(&source, &origin);
+        let own = parser.stylesheet()?;
+
+        let mut stylesheet = Self {
+            rules: vec![],
+            files: vec![],
+        };
+        let dir = path.parent().unwrap_or(Path::new(""));
+        importers.push(canonical(path));
+
+        for import in &parser.imports {
+            let imported = dir.join(&import.path);
+
+            if importers.contains(&canonical(&imported)) {
+                return Err(parser.error_at(
+                    import.position,
+                    &format!("{} imports itself", imported.display()),
+                ));
+            }
+
+            let message = || {
+                parser.error_at(
+                    import.position,
+                    &format!("failed to import {}", imported.display()),
+                )
+            };
+            stylesheet.extend(Self::load_imported(&imported, importers).with_context(message)?);
+        }
+
+        importers.pop();
+        stylesheet.extend(own);
+        stylesheet.files.push(path.to_path_buf());
+
+        Ok(stylesheet)
+    }
+
+    /// Appends the rules of `other`, which win over this stylesheet's when equally specific.
+    pub fn extend(&mut self, other: Stylesheet) {
+        self.rules.extend(other.rules);
+
+        for file in other.files {
+            if !self.files.contains(&file) {
+                self.files.push(file);
+            }
+        }
+    }
+
+    /// The files the stylesheet was loaded from, including the ones it imports.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Resolves the style of `node` and its descendants, replacing each node's style with the
+    /// rules that match it overridden by the style it had.
+    pub fn apply(&self, node: &mut Node) {
+        let mut styles = vec![];
+        self.resolve(node, &mut vec![], &mut styles);
+        assign(node, &mut styles.into_iter());
+    }
+
+    /// Collects the resolved styles of `node` and its descendants in depth-first order.
+    fn resolve<'a>(&self, node: &'a Node, ancestors: &mut Vec<&'a Node>, out: &mut Vec<Style>) {
+        let mut matching: Vec<&Rule> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.selector.matches(node, ancestors))
+            .collect();
+        // The sort is stable, so equally specific rules stay in source order.
+        matching.sort_by_key(|rule| rule.selector.specificity());
+
+        let mut style = Style::default();
+
+        for rule in matching {
+            style.merge(&rule.declarations);
+        }
+
+        style.merge(&node.style);
+        out.push(style);
+
+        ancestors.push(node);
+
+        for child in &node.children {
+            self.resolve(child, ancestors, out);
+        }
+
+        ancestors.pop();
+    }
+}
+
+impl Default for Stylesheet {
+    fn default() -> Self {
+        Self::parse(DEFAULT_STYLESHEET, "<default stylesheet>")
+            .expect("the default stylesheet is valid")
+    }
+}
+
+/// Where the user's stylesheet lives: `$XDG_CONFIG_HOME/rbar/style.css`, falling back to
+/// `~/.config/rbar/style.css`.
+pub fn user_path() -> Option<PathBuf> {
+    config::user_dir().map(|dir| dir.join("style.css"))
+}
+
+/// `path` with symlinks and `..` resolved where possible, to tell whether two paths name the
+/// same file.
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn assign(node: &mut Node, styles: &mut impl Iterator<Item = Style>) {
+    if let Some(style) = styles.next() {
+        node.style = style;
+    }
+
+    for child in &mut node.children {
+        assign(child, styles);
+    }
+}
+
+impl Selector {
+    /// Whether the selector matches `node`, whose ancestors are `ancestors` from the root
+    /// down.
+    fn matches(&self, node: &Node, ancestors: &[&Node]) -> bool {
+        let Some((subject, rest)) = self.compounds.split_last() else {
+            return false;
+        };
+
+        if !subject.matches(node) {
+            return false;
+        }
+
+        // Matching each compound against the nearest ancestor it fits is enough with only
+        // descendant combinators.
+        let mut ancestors = ancestors.iter().rev();
+
+        rest.iter()
+            .rev()
+            .all(|compound| ancestors.any(|ancestor| compound.matches(ancestor)))
+    }
+
+    /// CSS specificity: ids, then classes and pseudo-classes, then types.
+    fn specificity(&self) -> (usize, usize, usize) {
+        self.compounds
+            .iter()
+            .fold((0, 0, 0), |(ids, classes, kinds), compound| {
+                (
+                    ids + compound.id.is_some() as usize,
+                    classes
+                        + compound.classes.len()
+                        + compound.hover as usize
+                        + compound.active as usize,
+                    kinds + compound.kind.is_some() as usize,
+                )
+            })
+    }
+}
+
+impl Compound {
+    fn matches(&self, node: &Node) -> bool {
+        self.kind.as_ref().is_none_or(|kind| *kind == node.kind)
+            && self
+                .id
+                .as_ref()
+                .is_none_or(|id| node.id.as_ref() == Some(id))
+            && self.classes.iter().all(|class| node.has_class(class))
+            && (!self.hover || node.hovered)
+            && (!self.active || node.active)
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str, origin: &'a str) -> Self {
+        Self {
+            source,
+            origin,
+            position: 0,
+            imports: vec![],
+        }
+    }
+
+    fn stylesheet(&mut self) -> Result<Stylesheet> {
+        let mut rules = vec![];
+
+        loop {
+            self.skip_whitespace()?;
+
+            if self.position == self.source.len() {
+                return Ok(Stylesheet {
+                    rules,
+                    files: vec![],
+                });
+            }
+
+            if self.source[self.position..].starts_with("@import") {
+                if !rules.is_empty() {
+                    return Err(self.error("`@import` must come before any rule"));
+                }
+
+                self.import()?;
+                continue;
+            }
+
+            let selectors = self.selectors()?;
+            let declarations = self.declarations()?;
+
+            rules.extend(selectors.into_iter().map(|selector| Rule {
+                selector,
+                declarations: declarations.clone(),
+            }));
+        }
+    }
+
+    /// An `@import "path";` statement.
+    fn import(&mut self) -> Result<()> {
+        let position = self.position;
+        self.position += "@import".len();
+        self.skip_whitespace()?;
+
+        let quote = match self.peek() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(self.error("expected a quoted path")),
+        };
+        self.position += 1;
+
+        let Some(length) = self.source[self.position..].find(quote) else {
+            return Err(self.error("unterminated path"));
+        };
+        let path = PathBuf::from(&self.source[self.position..self.position + length]);
+        self.position += length + 1;
+
+        self.skip_whitespace()?;
+        self.expect(';')?;
+        self.imports.push(Import { path, position });
+
+        Ok(())
+    }
+
+    /// A comma separated selector list, up to the opening brace.
+    fn selectors(&mut self) -> Result<Vec<Selector>> {
+        let mut selectors = vec![];
+
+        loop {
+            let mut compounds = vec![];
+
+            loop {
+                self.skip_whitespace()?;
+
+                match self.peek() {
+                    Some('{' | ',') | None => break,
+                    _ => compounds.push(self.compound()?),
+                }
+            }
+
+            if compounds.is_empty() {
+                return Err(self.error("expected a selector"));
+            }
+
+            selectors.push(Selector { compounds });
+
+            if !self.eat(',') {
+                return Ok(selectors);
+            }
+        }
+    }
+
+    fn compound(&mut self) -> Result<Compound> {
+        let mut compound = Compound::default();
+        let start = self.position;
+
+        if !self.eat('*') && self.peek().is_some_and(is_identifier_char) {
+            compound.kind = Some(self.identifier()?);
+        }
+
+        loop {
+            if self.eat('#') {
+                compound.id = Some(self.identifier()?);
+            } else if self.eat('.') {
+                compound.classes.push(self.identifier()?);
+            } else if self.eat(':') {
+                let position = self.position;
+
+                match self.identifier()?.as_str() {
+                    "hover" => compound.hover = true,
+                    "active" => compound.active = true,
+                    other => {
+                        self.position = position;
+                        return Err(self.error(&format!(
+                            "unknown pseudo-class :{other}, expected :hover or :active"
+                        )));
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+
+        if self.position == start {
+            return Err(self.error("expected a selector"));
+        }
+
+        Ok(compound)
+    }
+
+    /// A brace enclosed block of `name: value;` declarations.
+    fn declarations(&mut self) -> Result<Style> {
+        let mut style = Style::default();
+        self.expect('{')?;
+
+        loop {
+            self.skip_whitespace()?;
+
+            if self.eat('}') {
+                return Ok(style);
+            }
+
+            let name_position = self.position;
+            let name = self.identifier()?;
+            self.skip_whitespace()?;
+            self.expect(':')?;
+            self.skip_whitespace()?;
+
+            let start = self.position;
+            let end = self.source[start..]
+                .find([';', '}'])
+                .map_or(self.source.len(), |offset| start + offset);
+            let value = self.source[start..end].trim();
+
+            match declare(&mut style, &name, value) {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.position = name_position;
+                    return Err(self.error(&format!("unknown property `{name}`")));
+                }
+                Err(message) => return Err(self.error(&message)),
+            }
+
+            self.position = end;
+            self.eat(';');
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String> {
+        let rest = &self.source[self.position..];
+        let length = rest
+            .find(|c: char| !is_identifier_char(c))
+            .unwrap_or(rest.len());
+
+        if length == 0 {
+            return Err(self.error("expected a name"));
+        }
+
+        self.position += length;
+
+        Ok(rest[..length].to_string())
+    }
+
+    /// Skips whitespace and `/* */` comments.
+    fn skip_whitespace(&mut self) -> Result<()> {
+        loop {
+            let rest = &self.source[self.position..];
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+
+            if !trimmed.starts_with("/*") {
+                return Ok(());
+            }
+
+            match trimmed.find("*/") {
+                Some(end) => self.position += end + 2,
+                None => return Err(self.error("unterminated comment")),
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{expected}`")))
+        }
+    }
+
+    /// An error at the current position.
+    fn error(&self, message: &str) -> Error {
+        self.error_at(self.position, message)
+    }
+
+    fn error_at(&self, position: usize, message: &str) -> Error {
+        let (line, column) = config::line_column(self.source, position);
+
+        anyhow!("{}:{line}:{column}: {message}", self.origin)
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Sets the property `name` in `style` from its CSS `value`, returning `false` if there is no
+/// such property.
+fn declare(style: &mut Style, name: &str, value: &str) -> Result<bool, String> {
+    match name {
+        "background" | "background-color" => style.background = Some(color(value)?),
+        "transition" => style.transition = Some(transition(value)?),
+        "color" => style.foreground = Some(color(value)?),
+        "font-family" => style.font_family = Some(font_family(value)?),
+        "font-size" => style.font_size = Some(length(value)?),
+        "font-weight" => style.font_weight = Some(font_weight(value)?),
+        "text-align" => {
+            style.text_align = Some(keyword(
+                value,
+                &[
+                    ("start", TextAlign::Start),
+                    ("left", TextAlign::Start),
+                    ("center", TextAlign::Center),
+                    ("end", TextAlign::End),
+                    ("right", TextAlign::End),
+                ],
+            )?)
+        }
+        "text-orientation" => {
+            style.text_orientation = Some(keyword(
+                value,
+                &[
+                    ("horizontal", TextOrientation::Horizontal),
+                    ("sideways", TextOrientation::Sideways),
+                    ("upright", TextOrientation::Upright),
+                ],
+            )?)
+        }
+        "overflow" => {
+            style.overflow = Some(keyword(
+                value,
+                &[
+                    ("visible", Overflow::Visible),
+                    ("clip", Overflow::Clip),
+                    ("hidden", Overflow::Clip),
+                    ("ellipsis", Overflow::Ellipsis),
+                ],
+            )?)
+        }
+        "opacity" => style.opacity = Some(number(value)?.clamp(0.0, 1.0)),
+        "border" => style.border = Some(border(value)?),
+        "border-radius" => style.radius = Some(radius(value)?),
+        "padding" => style.padding = Some(edges(value)?),
+        "margin" => style.margin = Some(edges(value)?),
+        "width" => style.width = Some(dimension(value)?),
+        "height" => style.height = Some(dimension(value)?),
+        "min-width" => style.min_width = Some(length(value)?),
+        "max-width" => style.max_width = Some(max_length(value)?),
+        "min-height" => style.min_height = Some(length(value)?),
+        "max-height" => style.max_height = Some(max_length(value)?),
+        "flex-direction" => {
+            style.direction = Some(keyword(
+                value,
+                &[("row", Direction::Row), ("column", Direction::Column)],
+            )?)
+        }
+        "justify-content" => {
+            style.justify = Some(keyword(
+                value,
+                &[
+                    ("start", Justify::Start),
+                    ("flex-start", Justify::Start),
+                    ("end", Justify::End),
+                    ("flex-end", Justify::End),
+                    ("center", Justify::Center),
+                    ("space-between", Justify::SpaceBetween),
+                    ("space-around", Justify::SpaceAround),
+                    ("space-evenly", Justify::SpaceEvenly),
+                ],
+            )?)
+        }
+        "align-items" => style.align = Some(align(value)?),
+        "align-self" => {
+            style.align_self = Some(match value {
+                "auto" => None,
+                value => Some(align(value)?),
+            })
+        }
+        "gap" => style.gap = Some(length(value)?),
+        "flex-basis" => style.basis = Some(dimension(value)?),
+        "flex-grow" => style.grow = Some(number(value)?.max(0.0)),
+        "flex-shrink" => style.shrink = Some(number(value)?.max(0.0)),
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+fn keyword<T: Copy>(value: &str, keywords: &[(&str, T)]) -> Result<T, String> {
+    keywords
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, keyword)| *keyword)
+        .ok_or_else(|| {
+            let names: Vec<&str> = keywords.iter().map(|(name, _)| *name).collect();
+            format!("expected one of {}, not `{value}`", names.join(", "))
+        })
+}
+
+fn number(value: &str) -> Result<f32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected a number, not `{value}`"))
+}
+
+/// A length in logical pixels, written with or without `px`.
+fn length(value: &str) -> Result<f32, String> {
+    value
+        .strip_suffix("px")
+        .unwrap_or(value)
+        .parse()
+        .map_err(|_| format!("expected a length like `4px`, not `{value}`"))
+}
+
+fn max_length(value: &str) -> Result<f32, String> {
+    match value {
+        "none" => Ok(f32::INFINITY),
+        value => length(value),
+    }
+}
+
+fn dimension(value: &str) -> Result<Length, String> {
+    match value {
+        "auto" => Ok(Length::Auto),
+        value => length(value).map(Length::Px),
+    }
+}
+
+/// One to four lengths, as in CSS shorthands: all sides, vertical and horizontal, top,
+/// horizontal and bottom, or all four clockwise from the top.
+fn lengths(value: &str) -> Result<[f32; 4], String> {
+    let lengths = value
+        .split_whitespace()
+        .map(length)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match lengths[..] {
+        [all] => Ok([all; 4]),
+        [vertical, horizontal] => Ok([vertical, horizontal, vertical, horizontal]),
+        [top, horizontal, bottom] => Ok([top, horizontal, bottom, horizontal]),
+        [top, right, bottom, left] => Ok([top, right, bottom, left]),
+        _ => Err(format!("expected one to four lengths, not `{value}`")),
+    }
+}
+
+fn edges(value: &str) -> Result<Edges, String> {
+    let [top, right, bottom, left] = lengths(value)?;
+
+    Ok(Edges {
+        top,
+        right,
+        bottom,
+        left,
+    })
+}
+
+/// Corner radii, clockwise from the top-left like CSS `border-radius`.
+fn radius(value: &str) -> Result<Radius, String> {
+    let [top_left, top_right, bottom_right, bottom_left] = lengths(value)?;
+
+    Ok(Radius(top_left, top_right, bottom_right, bottom_left))
+}
+
+/// `none`, or a width followed by a color.
+fn border(value: &str) -> Result<Border, String> {
+    if value == "none" {
+        return Ok(Border::NONE);
+    }
+
+    let (width, color_value) = value
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| format!("expected a width and a color like `1px #ffffff`, not `{value}`"))?;
+
+    Ok(Border {
+        width: length(width)?,
+        color: color(color_value.trim())?,
+    })
+}
+
+/// `none`, or a duration, a timing function or a duration followed by a timing function, like
+/// `150ms ease-out`. Timing functions default to `ease`, durations to none, which only springs
+/// do without since they run until they settle.
+fn transition(value: &str) -> Result<Transition, String> {
+    if value == "none" {
+        return Ok(Transition::NONE);
+    }
+
+    if let Ok(duration) = duration(value) {
+        return Ok(Transition::new(duration, Easing::EASE));
+    }
+
+    match value.split_once(char::is_whitespace) {
+        Some((first, rest)) if duration(first).is_ok() => {
+            Ok(Transition::new(duration(first)?, easing(rest.trim())?))
+        }
+        _ => Ok(Transition::new(Duration::ZERO, easing(value)?)),
+    }
+}
+
+/// A time in `s` or `ms`.
+fn duration(value: &str) -> Result<Duration, String> {
+    let nanoseconds = match value.strip_suffix("ms") {
+        Some(milliseconds) => milliseconds.parse::<f64>().map(|ms| ms * 1e6),
+        None => value
+            .strip_suffix('s')
+            .unwrap_or_default()
+            .parse::<f64>()
+            .map(|seconds| seconds * 1e9),
+    };
+
+    // Rounded to whole nanoseconds, so `150ms` is exactly that.
+    nanoseconds
+        .ok()
+        .filter(|nanoseconds| (0.0..u64::MAX as f64).contains(nanoseconds))
+        .map(|nanoseconds| Duration::from_nanos(nanoseconds.round() as u64))
+        .ok_or_else(|| format!("expected a duration like `150ms` or `0.2s`, not `{value}`"))
+}
+
+/// A timing function: a keyword, `cubic-bezier(x1, y1, x2, y2)` or
+/// `spring(stiffness, damping, mass)`.
+fn easing(value: &str) -> Result<Easing, String> {
+    if let Some(arguments) = function(value, "cubic-bezier") {
+        return match arguments?[..] {
+            [x1, y1, x2, y2] if (0.0..=1.0).contains(&x1) && (0.0..=1.0).contains(&x2) => {
+                Ok(Easing::CubicBezier(x1, y1, x2, y2))
+            }
+            _ => Err(format!(
+                "expected `cubic-bezier(x1, y1, x2, y2)` with x1 and x2 from 0 to 1, not `{value}`"
+            )),
+        };
+    }
+
+    if let Some(arguments) = function(value, "spring") {
+        return match arguments?[..] {
+            [stiffness, damping, mass] if stiffness > 0.0 && damping > 0.0 && mass > 0.0 => {
+                Ok(Easing::Spring {
+                    stiffness,
+                    damping,
+                    mass,
+                })
+            }
+            _ => Err(format!(
+                "expected `spring(stiffness, damping, mass)` with positive numbers, not `{value}`"
+            )),
+        };
+    }
+
+    keyword(
+        value,
+        &[
+            ("linear", Easing::Linear),
+            ("ease", Easing::EASE),
+            ("ease-in", Easing::EASE_IN),
+            ("ease-out", Easing::EASE_OUT),
+            ("ease-in-out", Easing::EASE_IN_OUT),
+        ],
+    )
+}
+
+/// The numbers `value` passes to the function `name`, or `None` if it calls something else.
+fn function(value: &str, name: &str) -> Option<Result<Vec<f32>, String>> {
+    let arguments = value
+        .strip_prefix(name)?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')?;
+
+    Some(
+        arguments
+            .split(',')
+            .map(|argument| number(argument.trim()))
+            .collect(),
+    )
+}
+
+fn align(value: &str) -> Result<Align, String> {
+    keyword(
+        value,
+        &[
+            ("start", Align::Start),
+            ("flex-start", Align::Start),
+            ("end", Align::End),
+            ("flex-end", Align::End),
+            ("center", Align::Center),
+            ("stretch", Align::Stretch),
+        ],
+    )
+}
+
+fn font_weight(value: &str) -> Result<u16, String> {
+    match value {
+        "normal" => Ok(400),
+        "bold" => Ok(700),
+        value => value
+            .parse()
+            .ok()
+            .filter(|weight| (1..=1000).contains(weight))
+            .ok_or_else(|| format!("expected normal, bold or 1 to 1000, not `{value}`")),
+    }
+}
+
+/// The first family of a comma separated list, with any quotes removed.
+fn font_family(value: &str) -> Result<String, String> {
+    let family = value
+        .split(',')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_matches(['"', '\'']);
+
+    if family.is_empty() {
+        return Err("expected a font family".to_string());
+    }
+
+    Ok(family.to_string())
+}
+
+/// A CSS color: `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`, `rgb(r, g, b)`, `rgba(r, g, b, a)` or
+/// one of a few names. Components are sRGB, like everywhere else in CSS.
+fn color(value: &str) -> Result<Color, String> {
+    let invalid =
+        || format!("expected a color like `#rrggbb` or `rgba(r, g, b, a)`, not `{value}`");
+
+    if let Some(hex) = value.strip_prefix('#') {
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        let channels: Vec<u8> = match digits.len() {
+            3 | 4 => digits.iter().map(|digit| digit * 17).collect(),
+            6 | 8 => digits
+                .chunks(2)
+                .map(|pair| pair[0] * 16 + pair[1])
+                .collect(),
+            _ => return Err(invalid()),
+        };
+        let channel = |index: usize| channels.get(index).map_or(1.0, |&c| c as f32 / 255.0);
+
+        return Ok(Color::from_srgb(
+            channel(0),
+            channel(1),
+            channel(2),
+            channel(3),
+        ));
+    }
+
+    if let Some(arguments) = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let components = arguments
+            .split([',', ' '])
+            .filter(|component| !component.is_empty())
+            .map(|component| component.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+
+        return match components[..] {
+            [red, green, blue] => Ok(Color::from_srgb(
+                red / 255.0,
+                green / 255.0,
+                blue / 255.0,
+                1.0,
+            )),
+            [red, green, blue, alpha] => Ok(Color::from_srgb(
+                red / 255.0,
+                green / 255.0,
+                blue / 255.0,
+                alpha.clamp(0.0, 1.0),
+            )),
+            _ => Err(invalid()),
+        };
+    }
+
+    let (red, green, blue, alpha) = match value {
+        "transparent" => (0.0, 0.0, 0.0, 0.0),
+        "black" => (0.0, 0.0, 0.0, 1.0),
+        "white" => (1.0, 1.0, 1.0, 1.0),
+        "gray" | "grey" => (0.5, 0.5, 0.5, 1.0),
+        "red" => (1.0, 0.0, 0.0, 1.0),
+        "green" => (0.0, 0.5, 0.0, 1.0),
+        "blue" => (0.0, 0.0, 1.0, 1.0),
+        "yellow" => (1.0, 1.0, 0.0, 1.0),
+        "orange" => (1.0, 0.65, 0.0, 1.0),
+        _ => return Err(invalid()),
+    };
+
+    Ok(Color::from_srgb(red, green, blue, alpha))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Stylesheet {
+        Stylesheet::parse(source, "test").unwrap()
+    }
+
+    fn error(source: &str) -> String {
+        Stylesheet::parse(source, "test").unwrap_err().to_string()
+    }
+
+    /// A fresh directory for the files of the test `name`.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rbar-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    /// A bar holding `clock` in its left section.
+    fn bar(clock: Node) -> Node {
+        let section = Node {
+            classes: vec!["left".to_string()],
+            ..Node::container("section", Style::default(), vec![clock])
+        };
+
+        Node::container("bar", Style::default(), vec![section])
+    }
+
+    /// A `clock` module with the id `main` and the `module` class, holding a `label`.
+    fn clock() -> Node {
+        Node {
+            id: Some("main".to_string()),
+            classes: vec!["module".to_string()],
+            ..Node::container(
+                "clock",
+                Style::default(),
+                vec![Node::text("label", "12:00", Style::default())],
+            )
+        }
+    }
+
+    /// The bar holding `clock` with `source` applied.
+    fn styled(source: &str, clock: Node) -> Node {
+        let mut root = bar(clock);
+        parse(source).apply(&mut root);
+
+        root
+    }
+
+    fn section(root: &Node) -> &Node {
+        &root.children[0]
+    }
+
+    fn clock_of(root: &Node) -> &Node {
+        &section(root).children[0]
+    }
+
+    fn label(root: &Node) -> &Node {
+        &clock_of(root).children[0]
+    }
+
+    /// The style `source` gives `clock`.
+    fn clock_style(source: &str, clock: Node) -> Style {
+        clock_of(&styled(source, clock)).style.clone()
+    }
+
+    #[test]
+    fn descendant_combinators_match_any_ancestor() {
+        let root = styled(
+            "bar label { opacity: 0.5; } .left #main { gap: 2; }",
+            clock(),
+        );
+
+        assert_eq!(label(&root).style.opacity, Some(0.5));
+        assert_eq!(clock_of(&root).style.gap, Some(2.0));
+    }
+
+    #[test]
+    fn descendant_combinators_need_every_ancestor() {
+        let root = styled(
+            ".right clock { opacity: 0.5; } clock section { opacity: 0.5; }",
+            clock(),
+        );
+
+        assert_eq!(clock_of(&root).style.opacity, None);
+        assert_eq!(section(&root).style.opacity, None);
+    }
+
+    #[test]
+    fn pseudo_classes_match_node_state() {
+        let source = "clock:hover { opacity: 0.5; } clock:active { gap: 2; }";
+
+        assert_eq!(clock_style(source, clock()).opacity, None);
+        assert_eq!(clock_style(source, clock()).gap, None);
+
+        let hovered = Node {
+            hovered: true,
+            ..clock()
+        };
+        assert_eq!(clock_style(source, hovered).opacity, Some(0.5));
+
+        let active = Node {
+            active: true,
+            ..clock()
+        };
+        assert_eq!(clock_style(source, active).gap, Some(2.0));
+    }
+
+    #[test]
+    fn comma_lists_share_declarations() {
+        let root = styled("section, label { opacity: 0.5; }", clock());
+
+        assert_eq!(section(&root).style.opacity, Some(0.5));
+        assert_eq!(clock_of(&root).style.opacity, None);
+        assert_eq!(label(&root).style.opacity, Some(0.5));
+    }
+
+    #[test]
+    fn more_specific_rules_win_regardless_of_order() {
+        let source = "
+            #main { opacity: 0.1; }
+            .module:hover { opacity: 0.2; gap: 1; }
+            bar .module { opacity: 0.3; gap: 2; font-size: 10; }
+            clock { opacity: 0.4; gap: 3; font-size: 11; flex-grow: 1; }
+        ";
+        let style = clock_style(
+            source,
+            Node {
+                hovered: true,
+                ..clock()
+            },
+        );
+
+        assert_eq!(style.opacity, Some(0.1));
+        assert_eq!(style.gap, Some(1.0));
+        assert_eq!(style.font_size, Some(10.0));
+        assert_eq!(style.grow, Some(1.0));
+    }
+
+    #[test]
+    fn later_rules_win_when_equally_specific() {
+        let style = clock_style(
+            ".module { gap: 1; } clock.module { gap: 2; } clock.module { gap: 3; }",
+            clock(),
+        );
+
+        assert_eq!(style.gap, Some(3.0));
+    }
+
+    #[test]
+    fn node_styles_win_over_rules() {
+        let clock = Node {
+            style: Style {
+                gap: Some(9.0),
+                ..Style::default()
+            },
+            ..clock()
+        };
+
+        assert_eq!(clock_style("#main { gap: 1; }", clock).gap, Some(9.0));
+    }
+
+    #[test]
+    fn parses_transitions() {
+        let style = |value: &str| {
+            let mut style = Style::default();
+            declare(&mut style, "transition", value).map(|_| style.transition.unwrap())
+        };
+
+        assert_eq!(style("none"), Ok(Transition::NONE));
+        assert_eq!(
+            style("150ms ease-out"),
+            Ok(Transition::new(
+                Duration::from_millis(150),
+                Easing::EASE_OUT
+            ))
+        );
+        assert_eq!(
+            style("0.5s"),
+            Ok(Transition::new(Duration::from_millis(500), Easing::EASE))
+        );
+        assert_eq!(
+            style("1s cubic-bezier(0.1, 0.7, 1.0, 0.1)"),
+            Ok(Transition::new(
+                Duration::from_secs(1),
+                Easing::CubicBezier(0.1, 0.7, 1.0, 0.1)
+            ))
+        );
+        assert_eq!(
+            style("spring(300, 20, 1)"),
+            Ok(Transition::new(
+                Duration::ZERO,
+                Easing::Spring {
+                    stiffness: 300.0,
+                    damping: 20.0,
+                    mass: 1.0
+                }
+            ))
+        );
+        assert!(style("150 ease").is_err());
+        assert!(style("cubic-bezier(2, 0, 0, 1)").is_err());
+        assert!(style("spring(300, 0, 1)").is_err());
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(
+            error("clock {\n    colour: red;\n}"),
+            "test:2:5: unknown property `colour`"
+        );
+        assert_eq!(
+            error("clock:focus { gap: 1; }"),
+            "test:1:7: unknown pseudo-class :focus, expected :hover or :active"
+        );
+        assert_eq!(error("clock { gap 1; }"), "test:1:13: expected `:`");
+        assert_eq!(error("clock"), "test:1:6: expected `{`");
+        // Columns count characters, not bytes.
+        assert_eq!(error("/* ü */ clock"), "test:1:14: expected `{`");
+        assert_eq!(error(", clock {}"), "test:1:1: expected a selector");
+        assert_eq!(
+            error("clock {}\n/* unfinished"),
+            "test:2:1: unterminated comment"
+        );
+    }
+
+    #[test]
+    fn imports_load_before_the_importing_rules() {
+        let dir = test_dir("imports");
+        fs::create_dir(dir.join("theme")).unwrap();
+        fs::write(
+            dir.join("style.css"),
+            "/* Colors */\n@import \"theme/colors.css\";\nclock { gap: 2; }",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("theme/colors.css"),
+            "@import 'base.css'; clock { gap: 1; flex-grow: 3; }",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("theme/base.css"),
+            "clock { flex-grow: 2; opacity: 0.5; }",
+        )
+        .unwrap();
+
+        let stylesheet = Stylesheet::load(&dir.join("style.css")).unwrap();
+        let mut root = bar(clock());
+        stylesheet.apply(&mut root);
+        let style = &clock_of(&root).style;
+
+        assert_eq!(style.gap, Some(2.0));
+        assert_eq!(style.grow, Some(3.0));
+        assert_eq!(style.opacity, Some(0.5));
+        assert_eq!(
+            stylesheet.files(),
+            [
+                dir.join("theme/base.css"),
+                dir.join("theme/colors.css"),
+                dir.join("style.css")
+            ]
+        );
+    }
+
+    #[test]
+    fn import_errors_point_at_the_import() {
+        let dir = test_dir("import-errors");
+        fs::write(dir.join("a.css"), "@import \"b.css\";").unwrap();
+        fs::write(dir.join("b.css"), "\n@import \"a.css\";").unwrap();
+        fs::write(dir.join("c.css"), "@import \"missing.css\";").unwrap();
+        let load = |name: &str| {
+            format!("{:#}", Stylesheet::load(&dir.join(name)).unwrap_err())
+                .replace(&format!("{}/", dir.display()), "")
+        };
+
+        assert_eq!(
+            load("a.css"),
+            "a.css:1:1: failed to import b.css: b.css:2:1: a.css imports itself"
+        );
+        assert!(
+            load("c.css").starts_with(
+                "c.css:1:1: failed to import missing.css: failed to read missing.css:"
+            )
+        );
+        assert_eq!(
+            error("@import \"a.css\";"),
+            "test:1:1: `@import` only works in stylesheet files"
+        );
+        assert_eq!(
+            error("clock {}\n@import \"a.css\";"),
+            "test:2:1: `@import` must come before any rule"
+        );
+        assert_eq!(error("@import a.css;"), "test:1:9: expected a quoted path");
+        assert_eq!(error("@import \"a.css\""), "test:1:16: expected `;`");
+    }
+
+    #[test]
+    fn errors_point_at_invalid_values() {
+        assert!(error("clock { gap: wide; }").starts_with("test:1:14: expected a length"));
+    }
+}
@@ -0,0 +1,709 @@
+//! The configuration file, `$XDG_CONFIG_HOME/rbar/config.toml`, such as
+//!
+//! ```toml
+//! # Relative to this file. Defaults to `style.css` next to it.
+//! stylesheet = "style.css"
+//!
+//! [bar]
+//! position = "bottom"          # top, bottom, left or right
+//! layer = "top"                # background, bottom, top or overlay
+//! thickness = 28               # fits the content when left out
+//! margin = 4                   # or { top = 4, right = 8, bottom = 0, left = 8 }
+//! exclusive-zone = "auto"      # auto, off or a number of pixels
+//! on-fullscreen = "hide"       # ignore, hide or lower
+//! auto-hide = { enabled = true, delay = "1.5s", hot-zone = 2 }
+//!
+//! [[modules.left]]
+//! kind = "label"
+//! text = "rbar"
+//!
+//! [[modules.right]]
+//! kind = "label"
+//! id = "greeting"
+//! classes = ["accent"]
+//! text = "hello"
+//!
+//! # Rules for particular outputs, applied in order on top of `[bar]`.
+//! [[output]]
+//! name = "HDMI-*"
+//! enabled = false
+//!
+//! # Outputs matching a rule with modules show those instead of `[modules]`.
+//! [[output]]
+//! name = "eDP-1"
+//!
+//! [[output.modules.center]]
+//! kind = "label"
+//! text = "laptop"
+//! ```
+//!
+//! Every key is optional except a module's `kind`. Unknown keys are errors, which point at
+//! the line and column of the key and suggest the closest known one.
+
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow};
+use regex::Regex;
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, MapAccess, Visitor, value::MapAccessDeserializer},
+};
+use smithay_client_toolkit::{
+    output::OutputInfo,
+    shell::wlr_layer::{self, Anchor},
+};
+
+/// Everything rbar can be configured with.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Stylesheet applied over the default rules, instead of the user's `style.css`.
+    pub stylesheet: Option<PathBuf>,
+    /// The bar every output gets unless a rule says otherwise.
+    pub bar: BarConfig,
+    pub modules: ModulesConfig,
+    /// Adjustments for particular outputs, applied in order on top of `bar`.
+    #[serde(rename = "output")]
+    pub outputs: Vec<OutputRule>,
+}
+
+/// The modules of every bar, by section.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModulesConfig {
+    pub left: Vec<ModuleConfig>,
+    pub center: Vec<ModuleConfig>,
+    pub right: Vec<ModuleConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModuleConfig {
+    /// Type of the module, which stylesheets select it by.
+    pub kind: String,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub classes: Vec<String>,
+    /// What the module shows until it has something else to show.
+    #[serde(default)]
+    pub text: String,
+}
+
+/// Where and how a bar sits on its output.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BarConfig {
+    /// Edge of the output the bar is attached to.
+    pub position: Edge,
+    pub layer: Layer,
+    /// Height of a horizontal bar or width of a vertical one in logical pixels, or `None` to fit
+    /// the content.
+    pub thickness: Option<u32>,
+    /// Distance from the edges of the output, in logical pixels. Only the margins of the edges
+    /// the bar is attached to apply.
+    #[serde(deserialize_with = "deserialize_margin")]
+    pub margin: Margin,
+    pub exclusive_zone: ExclusiveZone,
+    pub auto_hide: AutoHide,
+    /// What the bar does while the focused window is fullscreen on its output.
+    pub on_fullscreen: OnFullscreen,
+    /// The modules the bar shows, from `[modules]` or an output rule rather than `[bar]`.
+    #[serde(skip)]
+    pub modules: ModulesConfig,
+}
+
+/// Hiding the bar while it isn't used, leaving a thin strip along its edge that brings it back
+/// when the pointer enters.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AutoHide {
+    pub enabled: bool,
+    /// How long the bar stays after the pointer left it or it was revealed, written like
+    /// `500ms` or `1.5s`.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub delay: Duration,
+    /// Thickness of the strip left while hidden, in logical pixels.
+    pub hot_zone: u32,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    #[default]
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+/// Layer shell layers, from the bottom of the stack up.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    Background,
+    Bottom,
+    #[default]
+    Top,
+    Overlay,
+}
+
+/// Written as one number for every edge, or a table of the edges that have one.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Margin {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+/// Changes to the bar on the outputs matching every pattern given.
+///
+/// Outputs are matched by what the compositor tells about them, and a pattern for something it
+/// didn't tell never matches. A rule without patterns matches every output.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct OutputRule {
+    /// Connector name, such as `eDP-1` or `DP-2`.
+    pub name: Option<Pattern>,
+    pub make: Option<Pattern>,
+    pub model: Option<Pattern>,
+    /// Human readable description, usually make, model and connector together.
+    pub description: Option<Pattern>,
+    /// Whether matching outputs get a bar at all.
+    pub enabled: Option<bool>,
+    pub position: Option<Edge>,
+    pub layer: Option<Layer>,
+    pub thickness: Option<u32>,
+    #[serde(deserialize_with = "deserialize_optional_margin")]
+    pub margin: Option<Margin>,
+    pub exclusive_zone: Option<ExclusiveZone>,
+    /// Turns auto-hiding on or off, keeping the delay and hot zone of `bar`.
+    pub auto_hide: Option<bool>,
+    pub on_fullscreen: Option<OnFullscreen>,
+    /// Modules shown instead of `[modules]`.
+    pub modules: Option<ModulesConfig>,
+}
+
+/// A pattern matched against a whole string: a glob where `*` matches any run of characters
+/// and `?` any one character, or a regular expression between slashes, like `/^DP-[0-9]$/`,
+/// which matches anywhere unless anchored.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Glob(String),
+    Regex(Regex),
+}
+
+/// What a bar does while the focused window is fullscreen on its output, so it doesn't cover
+/// videos and games.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnFullscreen {
+    /// Stay where it is, which may be above the window.
+    #[default]
+    Ignore,
+    /// Disappear until the window leaves fullscreen.
+    Hide,
+    /// Move down to the bottom layer, under the window.
+    Lower,
+}
+
+/// How much space the bar keeps other surfaces, like maximized windows, away from.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ExclusiveZone {
+    /// The bar's thickness plus its margin from the edge it is attached to.
+    #[default]
+    Auto,
+    /// Reserve nothing, so windows may go underneath the bar.
+    Off,
+    /// Reserve a fixed distance from the edge, in logical pixels.
+    Fixed(u32),
+}
+
+impl Default for BarConfig {
+    fn default() -> Self {
+        Self {
+            position: Edge::Top,
+            layer: Layer::Top,
+            thickness: None,
+            margin: Margin::default(),
+            exclusive_zone: ExclusiveZone::Auto,
+            auto_hide: AutoHide::default(),
+            on_fullscreen: OnFullscreen::default(),
+            modules: ModulesConfig::default(),
+        }
+    }
+}
+
+impl Default for AutoHide {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            delay: Duration::from_secs(1),
+            hot_zone: 2,
+        }
+    }
+}
+
+impl Config {
+    /// The user's configuration, or the defaults if they don't have one.
+    pub fn load_user() -> Result<Self> {
+        match user_path().filter(|path| path.exists()) {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut config = Self::parse(&source, &path.display().to_string())?;
+
+        if let (Some(stylesheet), Some(dir)) = (&mut config.stylesheet, path.parent()) {
+            *stylesheet = dir.join(&*stylesheet);
+        }
+
+        Ok(config)
+    }
+
+    /// Parses `source`. Errors point at the offending line and column of `origin`.
+    pub fn parse(source: &str, origin: &str) -> Result<Self> {
+        toml::from_str(source).map_err(|error| {
+            let message = error.message().trim_end();
+            let message = match suggestion(message) {
+                Some(suggestion) => suggestion,
+                None => message.to_string(),
+            };
+
+            match error.span() {
+                Some(span) => {
+                    let (line, column) = line_column(source, span.start);
+                    anyhow!("{origin}:{line}:{column}: {message}")
+                }
+                None => anyhow!("{origin}: {message}"),
+            }
+        })
+    }
+
+    /// The bar of outputs no rule applies to.
+    pub fn default_bar(&self) -> BarConfig {
+        BarConfig {
+            modules: self.modules.clone(),
+            ..self.bar.clone()
+        }
+    }
+
+    /// The bar for the output described by `info`, or `None` if it shouldn't have one.
+    pub fn bar_for(&self, info: &OutputInfo) -> Option<BarConfig> {
+        let mut bar = self.default_bar();
+        let mut enabled = true;
+
+        for rule in self.outputs.iter().filter(|rule| rule.matches(info)) {
+            enabled = rule.enabled.unwrap_or(enabled);
+            bar.position = rule.position.unwrap_or(bar.position);
+            bar.layer = rule.layer.unwrap_or(bar.layer);
+            bar.thickness = rule.thickness.or(bar.thickness);
+            bar.margin = rule.margin.unwrap_or(bar.margin);
+            bar.exclusive_zone = rule.exclusive_zone.unwrap_or(bar.exclusive_zone);
+            bar.auto_hide.enabled = rule.auto_hide.unwrap_or(bar.auto_hide.enabled);
+            bar.on_fullscreen = rule.on_fullscreen.unwrap_or(bar.on_fullscreen);
+
+            if let Some(modules) = &rule.modules {
+                bar.modules = modules.clone();
+            }
+        }
+
+        enabled.then_some(bar)
+    }
+}
+
+impl BarConfig {
+    /// Layer surface size for a bar `thickness` thick. The compositor fills in the length
+    /// along the edge, which is left at 0.
+    pub fn size(&self, thickness: u32) -> (u32, u32) {
+        if self.position.is_vertical() {
+            (thickness, 0)
+        } else {
+            (0, thickness)
+        }
+    }
+
+    /// Layer shell exclusive zone for a bar `thickness` thick.
+    pub fn exclusive_zone(&self, thickness: u32) -> i32 {
+        match self.exclusive_zone {
+            ExclusiveZone::Auto => thickness as i32 + self.margin.at(self.position),
+            ExclusiveZone::Off => 0,
+            ExclusiveZone::Fixed(zone) => zone as i32,
+        }
+    }
+}
+
+impl Edge {
+    pub fn is_vertical(self) -> bool {
+        matches!(self, Self::Left | Self::Right)
+    }
+
+    /// Anchors stretching a bar along this edge.
+    pub fn anchor(self) -> Anchor {
+        match self {
+            Self::Top => Anchor::TOP | Anchor::LEFT | Anchor::RIGHT,
+            Self::Bottom => Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT,
+            Self::Left => Anchor::LEFT | Anchor::TOP | Anchor::BOTTOM,
+            Self::Right => Anchor::RIGHT | Anchor::TOP | Anchor::BOTTOM,
+        }
+    }
+}
+
+impl From<Layer> for wlr_layer::Layer {
+    fn from(layer: Layer) -> Self {
+        match layer {
+            Layer::Background => Self::Background,
+            Layer::Bottom => Self::Bottom,
+            Layer::Top => Self::Top,
+            Layer::Overlay => Self::Overlay,
+        }
+    }
+}
+
+impl OutputRule {
+    pub fn matches(&self, info: &OutputInfo) -> bool {
+        let matches = |pattern: &Option<Pattern>, value: Option<&str>| match (pattern, value) {
+            (None, _) => true,
+            (Some(pattern), Some(value)) => pattern.matches(value),
+            (Some(_), None) => false,
+        };
+
+        matches(&self.name, info.name.as_deref())
+            && matches(&self.make, Some(&info.make))
+            && matches(&self.model, Some(&info.model))
+            && matches(&self.description, info.description.as_deref())
+    }
+}
+
+impl Pattern {
+    /// Parses `pattern` as a regular expression if it is wrapped in slashes, as a glob
+    /// otherwise.
+    pub fn new(pattern: &str) -> Result<Self> {
+        match pattern
+            .strip_prefix('/')
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            Some(regex) => Ok(Self::Regex(
+                Regex::new(regex).with_context(|| format!("invalid regex {pattern}"))?,
+            )),
+            None => Ok(Self::Glob(pattern.to_string())),
+        }
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Glob(glob) => glob_matches(glob, value),
+            Self::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Glob(a), Self::Glob(b)) => a == b,
+            (Self::Regex(a), Self::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ExclusiveZone {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ZoneVisitor;
+
+        impl Visitor<'_> for ZoneVisitor {
+            type Value = ExclusiveZone;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("`auto`, `off` or a number of pixels")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                match value {
+                    "auto" => Ok(ExclusiveZone::Auto),
+                    "off" => Ok(ExclusiveZone::Off),
+                    other => Err(E::unknown_variant(other, &["auto", "off"])),
+                }
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                u32::try_from(value)
+                    .map(ExclusiveZone::Fixed)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+            }
+        }
+
+        deserializer.deserialize_any(ZoneVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+
+        Pattern::new(&pattern).map_err(|error| de::Error::custom(format!("{error:#}")))
+    }
+}
+
+impl Margin {
+    /// The margin from `edge`.
+    pub fn at(&self, edge: Edge) -> i32 {
+        match edge {
+            Edge::Top => self.top,
+            Edge::Bottom => self.bottom,
+            Edge::Left => self.left,
+            Edge::Right => self.right,
+        }
+    }
+}
+
+/// Whether `glob` matches all of `value`.
+fn glob_matches(glob: &str, value: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut g, mut v) = (0, 0);
+    // Where the last `*` was and how much of `value` it has taken so far, to backtrack to.
+    let mut star = None;
+
+    while v < value.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, v));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == value[v] => {
+                g += 1;
+                v += 1;
+            }
+            _ => match star {
+                Some((star_g, star_v)) => {
+                    g = star_g + 1;
+                    v = star_v + 1;
+                    star = Some((star_g, star_v + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+/// The line and column, both counted from 1, of the byte `offset` in `source`. Columns count
+/// characters rather than bytes, like editors do.
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// `$XDG_CONFIG_HOME/rbar`, or `~/.config/rbar` without it.
+pub fn user_dir() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config.join("rbar"))
+}
+
+pub fn user_path() -> Option<PathBuf> {
+    user_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Parses a margin from a single number for every edge or a table of edges.
+fn deserialize_margin<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Margin, D::Error> {
+    struct MarginVisitor;
+
+    impl<'de> Visitor<'de> for MarginVisitor {
+        type Value = Margin;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a number of pixels or a table of edges")
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+            let margin = i32::try_from(value)
+                .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))?;
+
+            Ok(Margin {
+                top: margin,
+                right: margin,
+                bottom: margin,
+                left: margin,
+            })
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            Margin::deserialize(MapAccessDeserializer::new(map))
+        }
+    }
+
+    deserializer.deserialize_any(MarginVisitor)
+}
+
+fn deserialize_optional_margin<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Margin>, D::Error> {
+    deserialize_margin(deserializer).map(Some)
+}
+
+/// Parses durations like `500ms`, `2s` and `1.5s`.
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    let (number, unit) = match value.strip_suffix("ms") {
+        Some(number) => (number, 0.001),
+        None => (value.strip_suffix('s').unwrap_or(""), 1.0),
+    };
+
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|number| Duration::try_from_secs_f64(number * unit).ok())
+        .ok_or_else(|| {
+            de::Error::custom(format!(
+                "invalid duration `{value}`, expected something like `500ms` or `1.5s`"
+            ))
+        })
+}
+
+/// Rewords an error about an unknown key or value to suggest the known one it is probably a
+/// typo of, if there is one close enough.
+///
+/// Serde words these errors like ``unknown field `positon`, expected one of `position`,
+/// `layer` ``, with unknown values as variants.
+fn suggestion(message: &str) -> Option<String> {
+    let (what, rest) = if let Some(rest) = message.strip_prefix("unknown field ") {
+        ("key", rest)
+    } else {
+        ("value", message.strip_prefix("unknown variant ")?)
+    };
+    let mut quoted = rest.split('`').skip(1).step_by(2);
+    let unknown = quoted.next()?;
+    let (_, known) = quoted
+        .map(|known| (strsim::osa_distance(unknown, known), known))
+        .filter(|&(distance, known)| distance <= known.len().max(3) / 3)
+        .min()?;
+
+    Some(format!(
+        "unknown {what} `{unknown}`, did you mean `{known}`?"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        Config::parse(source, "test").unwrap_err().to_string()
+    }
+
+    #[test]
+    fn suggests_close_keys_and_values() {
+        assert_eq!(
+            suggestion("unknown field `positon`, expected one of `position`, `layer`").as_deref(),
+            Some("unknown key `positon`, did you mean `position`?")
+        );
+        assert_eq!(
+            suggestion("unknown variant `bottm`, expected one of `top`, `bottom`").as_deref(),
+            Some("unknown value `bottm`, did you mean `bottom`?")
+        );
+    }
+
+    #[test]
+    fn suggests_nothing_for_distant_or_other_errors() {
+        assert_eq!(
+            suggestion("unknown field `colour`, expected `position` or `layer`"),
+            None
+        );
+        assert_eq!(suggestion("invalid type: string, expected u32"), None);
+    }
+
+    #[test]
+    fn globs_match_whole_values() {
+        let pattern = Pattern::new("HDMI-*").unwrap();
+
+        assert!(pattern.matches("HDMI-A-1"));
+        assert!(pattern.matches("HDMI-"));
+        assert!(!pattern.matches("DP-1"));
+        assert!(!pattern.matches("xHDMI-A-1"));
+
+        let pattern = Pattern::new("DP-?").unwrap();
+
+        assert!(pattern.matches("DP-2"));
+        assert!(!pattern.matches("DP-10"));
+    }
+
+    #[test]
+    fn globs_backtrack_over_stars() {
+        let pattern = Pattern::new("*-*-1").unwrap();
+
+        assert!(pattern.matches("HDMI-A-1"));
+        assert!(pattern.matches("a-b-c-1"));
+        assert!(!pattern.matches("HDMI-A-2"));
+        assert!(Pattern::new("é*").unwrap().matches("écran"));
+    }
+
+    #[test]
+    fn regexes_match_anywhere_unless_anchored() {
+        let pattern = Pattern::new("/DP-[0-9]/").unwrap();
+
+        assert!(matches!(pattern, Pattern::Regex(_)));
+        assert!(pattern.matches("eDP-1"));
+
+        let pattern = Pattern::new("/^DP-[0-9]$/").unwrap();
+
+        assert!(pattern.matches("DP-1"));
+        assert!(!pattern.matches("eDP-1"));
+        assert!(Pattern::new("/(/").is_err());
+    }
+
+    #[test]
+    fn counts_columns_in_characters() {
+        assert_eq!(line_column("abc", 0), (1, 1));
+        assert_eq!(line_column("abc\ndef", 5), (2, 2));
+        assert_eq!(line_column("é = 1\nä", 9), (2, 2));
+        assert_eq!(line_column("# é\nkey", 6), (2, 2));
+    }
+
+    #[test]
+    fn errors_point_at_the_key() {
+        assert_eq!(
+            error("[bar]\nlayer = \"top\"\npositon = \"top\"\n"),
+            "test:3:1: unknown key `positon`, did you mean `position`?"
+        );
+        assert_eq!(
+            error("# Ünïcödé\n[bar]\nthickness = \"wide\"\n"),
+            "test:3:13: invalid type: string \"wide\", expected u32"
+        );
+    }
+
+    #[test]
+    fn output_rules_take_modules() {
+        let config = Config::parse(
+            "[[output]]\nname = \"eDP-1\"\n\n[[output.modules.center]]\nkind = \"clock\"\n",
+            "test",
+        )
+        .unwrap();
+        let modules = config.outputs[0].modules.as_ref().unwrap();
+
+        assert_eq!(modules.center[0].kind, "clock");
+        assert!(modules.left.is_empty());
+        assert_eq!(config.default_bar().modules, ModulesConfig::default());
+    }
+}
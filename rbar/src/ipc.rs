@@ -0,0 +1,152 @@
+//! Commands sent to a running rbar over a Unix socket, one line per request:
+//!
+//! - `reveal [output]` slides auto-hiding bars in, until the auto-hide delay runs out.
+//! - `hide [output]` slides auto-hiding bars out right away.
+//!
+//! Without an output name, a command applies to every bar. The reply is a line with `ok`, or
+//! `error: ` and what went wrong.
+
+use std::{env, io::ErrorKind, path::PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, oneshot},
+};
+
+const USAGE: &str = "usage: rbar msg reveal|hide [output]";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Reveal { output: Option<String> },
+    Hide { output: Option<String> },
+}
+
+/// A command received from a client, waiting for the outcome to reply with.
+pub struct Request {
+    pub command: Command,
+    pub reply: oneshot::Sender<Result<()>>,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut words = line.split_whitespace();
+        let command = words.next().ok_or_else(|| anyhow!("empty command"))?;
+        let output = words.next().map(str::to_string);
+
+        if let Some(extra) = words.next() {
+            bail!("unexpected argument {extra}");
+        }
+
+        match command {
+            "reveal" => Ok(Self::Reveal { output }),
+            "hide" => Ok(Self::Hide { output }),
+            other => bail!("unknown command {other}"),
+        }
+    }
+}
+
+/// Where the socket of the rbar on the current Wayland display lives:
+/// `$XDG_RUNTIME_DIR/rbar-$WAYLAND_DISPLAY.sock`.
+pub fn socket_path() -> Result<PathBuf> {
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .context("XDG_RUNTIME_DIR is not set")?;
+    let display = env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_string());
+
+    Ok(PathBuf::from(runtime_dir).join(format!("rbar-{display}.sock")))
+}
+
+/// Starts accepting commands, which arrive on the returned channel.
+pub async fn listen() -> Result<mpsc::UnboundedReceiver<Request>> {
+    let path = socket_path()?;
+
+    // A socket nobody answers on was left behind by an rbar that didn't exit cleanly.
+    if UnixStream::connect(&path).await.is_ok() {
+        bail!("rbar is already running on {}", path.display());
+    }
+
+    match std::fs::remove_file(&path) {
+        Err(error) if error.kind() != ErrorKind::NotFound => {
+            return Err(error).with_context(|| format!("failed to remove {}", path.display()));
+        }
+        _ => {}
+    }
+
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("failed to listen on {}", path.display()))?;
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve(stream, sender.clone()));
+                }
+                Err(error) => log::warn!("failed to accept an IPC connection: {error}"),
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// Answers the requests of one client until it disconnects.
+async fn serve(stream: UnixStream, requests: mpsc::UnboundedSender<Request>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let outcome = match Command::parse(&line) {
+            Ok(command) => {
+                let (reply, outcome) = oneshot::channel();
+
+                if requests.send(Request { command, reply }).is_err() {
+                    return;
+                }
+
+                outcome
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow!("rbar is shutting down")))
+            }
+            Err(error) => Err(error),
+        };
+        let response = match outcome {
+            Ok(()) => "ok\n".to_string(),
+            Err(error) => format!("error: {error:#}\n"),
+        };
+
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Sends the command in `args` to the running rbar, for `rbar msg`.
+pub async fn send(args: &[String]) -> Result<()> {
+    if args.is_empty() {
+        bail!("missing command\n{USAGE}");
+    }
+
+    let line = args.join(" ");
+    Command::parse(&line).map_err(|error| anyhow!("{error}\n{USAGE}"))?;
+
+    let path = socket_path()?;
+    let stream = UnixStream::connect(&path)
+        .await
+        .with_context(|| format!("failed to connect to rbar on {}", path.display()))?;
+    let (reader, mut writer) = stream.into_split();
+    writer.write_all(format!("{line}\n").as_bytes()).await?;
+
+    let response = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await?
+        .ok_or_else(|| anyhow!("rbar closed the connection without replying"))?;
+
+    match response.strip_prefix("error: ") {
+        Some(error) => bail!("{error}"),
+        None => Ok(()),
+    }
+}
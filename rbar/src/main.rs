@@ -1,37 +1,137 @@
+use std::{io::ErrorKind, os::fd::AsRawFd};
+
 use anyhow::Result;
-use smithay_client_toolkit::{
-    compositor::CompositorState, output::OutputState, registry::RegistryState, seat::SeatState,
-    shell::wlr_layer::LayerShell,
+use tokio::{io::unix::AsyncFd, sync::mpsc};
+use wayland_client::{
+    Connection, EventQueue, QueueHandle, backend::WaylandError, globals::registry_queue_init,
 };
-use wayland_client::{Connection, QueueHandle, globals::registry_queue_init};
 
-use crate::app::App;
+use crate::{app::App, ipc::Request, watch::Watcher};
 
+mod animation;
 mod app;
+mod backend;
+mod backoff;
 mod bar;
+mod config;
+mod ipc;
+mod scale;
+mod screenshot;
+mod toplevel;
+mod watch;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    env_logger::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().is_some_and(|command| command == "screenshot") {
+        return screenshot::run(&args[1..]).await;
+    }
+
+    if args.first().is_some_and(|command| command == "msg") {
+        return ipc::send(&args[1..]).await;
+    }
+
     let conn = Connection::connect_to_env()?;
     let (globals, mut event_queue) = registry_queue_init(&conn)?;
     let qh: QueueHandle<App> = event_queue.handle();
+    let mut app = App::new(&globals, &qh).await?;
+
+    // The bar works without IPC, just without anything to control it from outside.
+    let requests = match ipc::listen().await {
+        Ok(requests) => requests,
+        Err(error) => {
+            log::warn!("not accepting commands: {error:#}");
+            mpsc::unbounded_channel().1
+        }
+    };
 
-    let compositor_state = CompositorState::bind(&globals, &qh)?;
-    let layer_shell = LayerShell::bind(&globals, &qh)?;
-    let output_state = OutputState::new(&globals, &qh);
-    let seat_state = SeatState::new(&globals, &qh);
-    let registry_state = RegistryState::new(&globals);
-
-    let mut app = App::new(
-        output_state,
-        seat_state,
-        layer_shell,
-        compositor_state,
-        registry_state,
-    )
-    .await?;
+    // Likewise, the configuration is only reloaded if its files can be watched.
+    let watcher = match Watcher::new(&app.config_files()) {
+        Ok(watcher) => Some(watcher),
+        Err(error) => {
+            log::warn!("not reloading the configuration on changes: {error:#}");
+            None
+        }
+    };
+
+    run(&conn, &mut event_queue, &mut app, requests, watcher).await
+}
+
+/// Dispatches Wayland events and IPC commands and reloads the configuration when it changes,
+/// waking up in between whenever the app has something due.
+async fn run(
+    conn: &Connection,
+    event_queue: &mut EventQueue<App>,
+    app: &mut App,
+    mut requests: mpsc::UnboundedReceiver<Request>,
+    mut watcher: Option<Watcher>,
+) -> Result<()> {
+    let qh = event_queue.handle();
+    let fd = AsyncFd::new(conn.backend().poll_fd().as_raw_fd())?;
 
     loop {
-        event_queue.blocking_dispatch(&mut app)?;
+        event_queue.dispatch_pending(app)?;
+        app.wake(conn, &qh);
+        event_queue.flush()?;
+
+        // Events may have been queued while dispatching, in which case there is no reading.
+        let Some(guard) = event_queue.prepare_read() else {
+            continue;
+        };
+        let wakeup = app.next_wakeup();
+        let sleep = async {
+            match wakeup {
+                Some(wakeup) => tokio::time::sleep_until(wakeup.into()).await,
+                None => std::future::pending().await,
+            }
+        };
+        let changed = async {
+            match &mut watcher {
+                Some(watcher) => watcher.changed().await,
+                None => std::future::pending().await,
+            }
+        };
+
+        // Anything but events cancels the read by dropping the guard.
+        tokio::select! {
+            readable = fd.readable() => {
+                let mut readable = readable?;
+
+                match guard.read() {
+                    Ok(_) => readable.retain_ready(),
+                    Err(WaylandError::Io(error)) if error.kind() == ErrorKind::WouldBlock => {
+                        readable.clear_ready()
+                    }
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            Some(request) = requests.recv() => {
+                drop(guard);
+                let outcome = app.handle_command(&qh, &request.command);
+                let _ = request.reply.send(outcome);
+            }
+            changed = changed => {
+                drop(guard);
+
+                match changed {
+                    Ok(()) => {
+                        app.reload(conn, &qh);
+
+                        // The configuration may name another stylesheet now.
+                        if let Some(watcher) = &mut watcher {
+                            watcher.watch(&app.config_files());
+                        }
+                    }
+                    Err(error) => {
+                        log::warn!("stopped watching the configuration: {error:#}");
+                        watcher = None;
+                    }
+                }
+            }
+            () = sleep => drop(guard),
+        }
     }
 }
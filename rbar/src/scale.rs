@@ -0,0 +1,172 @@
+use wayland_client::{
+    Connection, Dispatch, QueueHandle, globals::GlobalList, protocol::wl_surface::WlSurface,
+};
+use wayland_protocols::wp::{
+    fractional_scale::v1::client::{
+        wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+        wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+    },
+    viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
+};
+
+use crate::app::App;
+
+/// `wp_fractional_scale_v1` reports scales as multiples of 1/120.
+const FRACTIONAL_SCALE_DENOMINATOR: f64 = 120.0;
+
+/// The globals needed for fractional scaling. Either may be missing, in which case surfaces
+/// fall back to integer scales through `wl_surface.set_buffer_scale`.
+pub struct ScaleState {
+    fractional_scale_manager: Option<WpFractionalScaleManagerV1>,
+    viewporter: Option<WpViewporter>,
+}
+
+/// The scale of one surface, and the protocol objects used to apply it.
+pub struct SurfaceScale {
+    integer: i32,
+    fractional: Option<f64>,
+    fractional_scale: Option<WpFractionalScaleV1>,
+    viewport: Option<WpViewport>,
+}
+
+impl ScaleState {
+    pub fn bind(globals: &GlobalList, qh: &QueueHandle<App>) -> Self {
+        Self {
+            fractional_scale_manager: globals.bind(qh, 1..=1, ()).ok(),
+            viewporter: globals.bind(qh, 1..=1, ()).ok(),
+        }
+    }
+
+    /// Starts tracking the scale of `surface`, which starts out at the `integer` scale of the
+    /// output it is created on.
+    pub fn track(&self, qh: &QueueHandle<App>, surface: &WlSurface, integer: i32) -> SurfaceScale {
+        // A fractional scale is only usable with a viewport to map the buffer back onto the
+        // surface, so ask for neither unless both are there.
+        let (fractional_scale, viewport) = match (&self.fractional_scale_manager, &self.viewporter)
+        {
+            (Some(manager), Some(viewporter)) => (
+                Some(manager.get_fractional_scale(surface, qh, surface.clone())),
+                Some(viewporter.get_viewport(surface, qh, ())),
+            ),
+            _ => (None, None),
+        };
+
+        SurfaceScale {
+            integer: integer.max(1),
+            fractional: None,
+            fractional_scale,
+            viewport,
+        }
+    }
+}
+
+impl SurfaceScale {
+    /// Buffer pixels per logical pixel.
+    pub fn factor(&self) -> f64 {
+        self.fractional.unwrap_or(self.integer as f64)
+    }
+
+    /// Records the integer scale reported for the surface, returning whether it changed.
+    pub fn set_integer(&mut self, scale: i32) -> bool {
+        let scale = scale.max(1);
+        let changed = self.integer != scale && self.fractional.is_none();
+        self.integer = scale;
+
+        changed
+    }
+
+    /// Records the preferred fractional scale, returning whether it changed.
+    pub fn set_fractional(&mut self, scale: f64) -> bool {
+        let changed = self.fractional != Some(scale);
+        self.fractional = Some(scale);
+
+        changed
+    }
+
+    /// Size of the buffer for a surface `width` x `height` logical pixels large.
+    pub fn buffer_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let factor = self.factor();
+
+        (
+            (width as f64 * factor).round() as u32,
+            (height as f64 * factor).round() as u32,
+        )
+    }
+
+    /// Tells the compositor how the next buffer attached to `surface` maps onto its `width` x
+    /// `height` logical pixels. Takes effect on the next commit.
+    pub fn apply(&self, surface: &WlSurface, width: u32, height: u32) {
+        match &self.viewport {
+            Some(viewport) if self.fractional.is_some() => {
+                surface.set_buffer_scale(1);
+                viewport.set_destination(width as i32, height as i32);
+            }
+            _ => surface.set_buffer_scale(self.integer),
+        }
+    }
+}
+
+impl Drop for SurfaceScale {
+    fn drop(&mut self) {
+        if let Some(fractional_scale) = &self.fractional_scale {
+            fractional_scale.destroy();
+        }
+
+        if let Some(viewport) = &self.viewport {
+            viewport.destroy();
+        }
+    }
+}
+
+impl Dispatch<WpFractionalScaleV1, WlSurface> for App {
+    fn event(
+        state: &mut Self,
+        _proxy: &WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        surface: &WlSurface,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            state.set_fractional_scale(qh, surface, scale as f64 / FRACTIONAL_SCALE_DENOMINATOR);
+        }
+    }
+}
+
+// The manager, viewporter and viewport interfaces have no events.
+
+impl Dispatch<WpFractionalScaleManagerV1, ()> for App {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WpFractionalScaleManagerV1,
+        _event: <WpFractionalScaleManagerV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WpViewporter, ()> for App {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WpViewporter,
+        _event: <WpViewporter as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WpViewport, ()> for App {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WpViewport,
+        _event: <WpViewport as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
@@ -0,0 +1,269 @@
+use std::{path::PathBuf, time::Instant};
+
+use anyhow::{Context, Result, anyhow, bail};
+use rbar_render::{Rect, Renderer, RgbaImage, Scene};
+
+use crate::{
+    bar::{Sections, draw_modules, stylesheet::Stylesheet},
+    config::{Config, Edge, ModuleConfig, ModulesConfig},
+};
+
+const DEFAULT_WIDTH: u32 = 1920;
+const DEFAULT_HEIGHT: u32 = 30;
+
+const USAGE: &str = "usage: rbar screenshot [--config <path>] [--width <px>] [--height <px>] [--scale <factor>] [--position top|bottom|left|right] [--software] <out.png>";
+
+/// Command line options. Those left unset default to the bar in the configuration.
+struct Options {
+    config: Option<PathBuf>,
+    width: Option<u32>,
+    height: Option<u32>,
+    scale: f32,
+    position: Option<Edge>,
+    software: bool,
+    output: PathBuf,
+}
+
+/// Renders the bar offscreen and saves it as a PNG, without connecting to Wayland.
+pub async fn run(args: &[String]) -> Result<()> {
+    let options = parse(args)?;
+    let config = match &options.config {
+        Some(path) => Config::load(path)?,
+        None => Config::load_user()?,
+    };
+    let stylesheet = Stylesheet::load_user(&config)?;
+    let renderer = if options.software {
+        Renderer::new_software().await?
+    } else {
+        Renderer::new().await?
+    };
+
+    render(&renderer, &options, &config, &stylesheet)?
+        .save(&options.output)
+        .with_context(|| format!("failed to write {}", options.output.display()))
+}
+
+/// Draws the bar `config` and `stylesheet` describe, as `options` say.
+///
+/// Without any configured modules, a few samples stand in for them, so the image still shows
+/// what the stylesheet does to modules.
+fn render(
+    renderer: &Renderer,
+    options: &Options,
+    config: &Config,
+    stylesheet: &Stylesheet,
+) -> Result<RgbaImage> {
+    let position = options.position.unwrap_or(config.bar.position);
+    // Vertical bars run down the output, so the default size turns with them.
+    let thickness = config.bar.thickness.unwrap_or(DEFAULT_HEIGHT);
+    let (default_width, default_height) = if position.is_vertical() {
+        (thickness, DEFAULT_WIDTH)
+    } else {
+        (DEFAULT_WIDTH, thickness)
+    };
+    let width = options.width.unwrap_or(default_width);
+    let height = options.height.unwrap_or(default_height);
+    let modules = if config.modules == ModulesConfig::default() {
+        sample_modules()
+    } else {
+        config.modules.clone()
+    };
+
+    // Width and height are logical pixels, like the bar's own size, so the image is scaled up.
+    let mut target = renderer.create_offscreen_renderer(
+        (width as f32 * options.scale).round() as u32,
+        (height as f32 * options.scale).round() as u32,
+    );
+    target.set_scale(options.scale);
+    let mut scene = Scene::new();
+    draw_modules(
+        &mut scene,
+        &mut Sections::new(&modules),
+        stylesheet,
+        position,
+        Rect::new(0.0, 0.0, width as f32, height as f32),
+        &target,
+        Instant::now(),
+    );
+
+    target.render(&scene)
+}
+
+fn sample_modules() -> ModulesConfig {
+    let module = |kind: &str, text: &str| ModuleConfig {
+        kind: kind.to_string(),
+        id: None,
+        classes: vec![],
+        text: text.to_string(),
+    };
+
+    ModulesConfig {
+        left: vec![module("workspaces", "1 2 3")],
+        center: vec![module("clock", "12:00")],
+        right: vec![module("volume", "40%"), module("battery", "87%")],
+    }
+}
+
+/// Parses the command line.
+fn parse(args: &[String]) -> Result<Options> {
+    let mut config = None;
+    let mut width = None;
+    let mut height = None;
+    let mut scale = 1.0;
+    let mut position = None;
+    let mut software = false;
+    let mut output = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config = Some(parse_path(args.next(), "--config")?),
+            "--width" => width = Some(parse_size(args.next(), "--width")?),
+            "--height" => height = Some(parse_size(args.next(), "--height")?),
+            "--scale" => scale = parse_scale(args.next())?,
+            "--position" => position = Some(parse_position(args.next())?),
+            "--software" => software = true,
+            flag if flag.starts_with("--") => bail!("unknown option {flag}\n{USAGE}"),
+            path if output.is_none() => output = Some(PathBuf::from(path)),
+            extra => bail!("unexpected argument {extra}\n{USAGE}"),
+        }
+    }
+
+    Ok(Options {
+        config,
+        width,
+        height,
+        scale,
+        position,
+        software,
+        output: output.ok_or_else(|| anyhow!("missing output path\n{USAGE}"))?,
+    })
+}
+
+fn parse_path(value: Option<&String>, flag: &str) -> Result<PathBuf> {
+    value
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!("{flag} needs a value\n{USAGE}"))
+}
+
+fn parse_size(value: Option<&String>, flag: &str) -> Result<u32> {
+    let value = value.ok_or_else(|| anyhow!("{flag} needs a value\n{USAGE}"))?;
+    let size: u32 = value
+        .parse()
+        .with_context(|| format!("{flag} must be a positive number of pixels"))?;
+
+    if size == 0 {
+        bail!("{flag} must be a positive number of pixels");
+    }
+
+    Ok(size)
+}
+
+fn parse_scale(value: Option<&String>) -> Result<f32> {
+    let value = value.ok_or_else(|| anyhow!("--scale needs a value\n{USAGE}"))?;
+    let scale: f32 = value.parse().context("--scale must be a positive number")?;
+
+    if !(scale > 0.0 && scale.is_finite()) {
+        bail!("--scale must be a positive number");
+    }
+
+    Ok(scale)
+}
+
+fn parse_position(value: Option<&String>) -> Result<Edge> {
+    match value.map(String::as_str) {
+        Some("top") => Ok(Edge::Top),
+        Some("bottom") => Ok(Edge::Bottom),
+        Some("left") => Ok(Edge::Left),
+        Some("right") => Ok(Edge::Right),
+        Some(other) => bail!("--position must be top, bottom, left or right, not {other}"),
+        None => bail!("--position needs a value\n{USAGE}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::Path};
+
+    use super::*;
+
+    /// The image the sample bar should render to. Set `RBAR_UPDATE_GOLDEN` to write it again
+    /// after changing how bars look on purpose.
+    const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/screenshot.png");
+    /// The only font the sample bar is drawn with, so the host's fonts don't matter.
+    const FONT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/Inter-Regular.ttf");
+    /// Largest difference in any channel still counted as the same, for rounding differences
+    /// between software rasterizers.
+    const TOLERANCE: u8 = 2;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_config_path() {
+        let options = parse(&args(&[
+            "--config", "bar.toml", "--width", "400", "out.png",
+        ]))
+        .unwrap();
+
+        assert_eq!(options.config, Some(PathBuf::from("bar.toml")));
+        assert_eq!(options.width, Some(400));
+        assert_eq!(options.height, None);
+        assert_eq!(options.output, PathBuf::from("out.png"));
+        assert!(parse(&args(&["out.png", "--config"])).is_err());
+    }
+
+    #[tokio::test]
+    async fn renders_sample_bar() {
+        assert!(Path::new(FONT).exists(), "{FONT} is missing");
+        // Rendering needs a software adapter such as lavapipe or llvmpipe, which not every
+        // machine has.
+        let renderer = match Renderer::new_software_with_fonts(&[Path::new(FONT)]).await {
+            Ok(renderer) => renderer,
+            Err(error) => {
+                eprintln!("skipping, no software renderer: {error:#}");
+                return;
+            }
+        };
+        let options = Options {
+            config: None,
+            width: Some(400),
+            height: Some(30),
+            scale: 1.0,
+            position: Some(Edge::Top),
+            software: true,
+            output: PathBuf::new(),
+        };
+        let mut stylesheet = Stylesheet::default();
+        stylesheet.extend(
+            Stylesheet::parse(
+                "bar { background: #1e1e2e; } clock { background: #45475a; }",
+                "test",
+            )
+            .unwrap(),
+        );
+        let image = render(&renderer, &options, &Config::default(), &stylesheet).unwrap();
+
+        if env::var_os("RBAR_UPDATE_GOLDEN").is_some() {
+            image.save(GOLDEN).unwrap();
+            return;
+        }
+
+        let golden = image::open(GOLDEN).unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), golden.dimensions());
+        let differing = image
+            .pixels()
+            .zip(golden.pixels())
+            .filter(|(pixel, expected)| {
+                pixel
+                    .0
+                    .iter()
+                    .zip(expected.0)
+                    .any(|(channel, expected)| channel.abs_diff(expected) > TOLERANCE)
+            })
+            .count();
+
+        assert_eq!(differing, 0, "{differing} pixels differ from {GOLDEN}");
+    }
+}
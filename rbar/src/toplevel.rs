@@ -0,0 +1,149 @@
+use wayland_client::{
+    Connection, Dispatch, QueueHandle, event_created_child, globals::GlobalList,
+    protocol::wl_output::WlOutput,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use crate::app::App;
+
+/// The windows of other clients, as far as bars care: which outputs they are on and whether
+/// they are fullscreen and focused. Without `zwlr_foreign_toplevel_manager_v1` no window is
+/// ever known.
+pub struct ToplevelState {
+    manager: Option<ZwlrForeignToplevelManagerV1>,
+    toplevels: Vec<Toplevel>,
+}
+
+struct Toplevel {
+    handle: ZwlrForeignToplevelHandleV1,
+    /// State as of the last `done` event.
+    current: ToplevelInfo,
+    /// State being received until the next `done` event.
+    pending: ToplevelInfo,
+}
+
+#[derive(Clone, Default)]
+struct ToplevelInfo {
+    outputs: Vec<WlOutput>,
+    fullscreen: bool,
+    activated: bool,
+}
+
+impl ToplevelState {
+    pub fn bind(globals: &GlobalList, qh: &QueueHandle<App>) -> Self {
+        Self {
+            manager: globals.bind(qh, 1..=3, ()).ok(),
+            toplevels: vec![],
+        }
+    }
+
+    /// Whether the focused window is fullscreen on `output`. Fullscreen windows that aren't
+    /// focused, such as ones on other workspaces or behind other windows, don't count.
+    pub fn is_fullscreen_on(&self, output: &WlOutput) -> bool {
+        self.toplevels.iter().any(|toplevel| {
+            let info = &toplevel.current;
+            info.fullscreen && info.activated && info.outputs.contains(output)
+        })
+    }
+
+    fn toplevel(&mut self, handle: &ZwlrForeignToplevelHandleV1) -> Option<&mut Toplevel> {
+        self.toplevels
+            .iter_mut()
+            .find(|toplevel| &toplevel.handle == handle)
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for App {
+    fn event(
+        state: &mut Self,
+        _proxy: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let toplevels = state.toplevel_state();
+
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                toplevels.toplevels.push(Toplevel {
+                    handle: toplevel,
+                    current: ToplevelInfo::default(),
+                    pending: ToplevelInfo::default(),
+                });
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                toplevels.manager = None;
+                toplevels.toplevels.clear();
+                state.fullscreen_changed(qh);
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(App, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for App {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let toplevels = state.toplevel_state();
+
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output } => {
+                if let Some(toplevel) = toplevels.toplevel(handle) {
+                    toplevel.pending.outputs.push(output);
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputLeave { output } => {
+                if let Some(toplevel) = toplevels.toplevel(handle) {
+                    toplevel
+                        .pending
+                        .outputs
+                        .retain(|entered| *entered != output);
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: states } => {
+                if let Some(toplevel) = toplevels.toplevel(handle) {
+                    // The states are an array of native-endian 32-bit enum values.
+                    let states: Vec<u32> = states
+                        .chunks_exact(4)
+                        .map(|value| u32::from_ne_bytes([value[0], value[1], value[2], value[3]]))
+                        .collect();
+                    let has = |state: zwlr_foreign_toplevel_handle_v1::State| {
+                        states.contains(&(state as u32))
+                    };
+                    toplevel.pending.fullscreen =
+                        has(zwlr_foreign_toplevel_handle_v1::State::Fullscreen);
+                    toplevel.pending.activated =
+                        has(zwlr_foreign_toplevel_handle_v1::State::Activated);
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done => {
+                if let Some(toplevel) = toplevels.toplevel(handle) {
+                    toplevel.current = toplevel.pending.clone();
+                    state.fullscreen_changed(qh);
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                toplevels
+                    .toplevels
+                    .retain(|toplevel| &toplevel.handle != handle);
+                handle.destroy();
+                state.fullscreen_changed(qh);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use tokio::io::unix::AsyncFd;
+
+/// How long files have to stay unchanged before a change counts, since editors save in
+/// several steps, like truncating a file before writing it.
+const SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// Whatever replaces, writes or removes a file in a watched directory.
+const WATCH_MASK: WatchMask = WatchMask::MODIFY
+    .union(WatchMask::CLOSE_WRITE)
+    .union(WatchMask::CREATE)
+    .union(WatchMask::DELETE)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::MOVED_TO);
+
+/// Notices changes to a set of files, such as the configuration and the stylesheet.
+///
+/// Files are watched through their directories, so that files which don't exist yet or are
+/// replaced rather than written to, as many editors save, are noticed too.
+pub struct Watcher {
+    inotify: AsyncFd<Inotify>,
+    /// Watched directories, with the names of the watched files in each.
+    dirs: Vec<(WatchDescriptor, Vec<OsString>)>,
+    /// Set when a watched file changed, until the changes settle.
+    changed: bool,
+}
+
+impl Watcher {
+    pub fn new(paths: &[PathBuf]) -> Result<Self> {
+        let mut watcher = Self {
+            inotify: AsyncFd::new(Inotify::init()?)?,
+            dirs: vec![],
+            changed: false,
+        };
+        watcher.watch(paths);
+
+        Ok(watcher)
+    }
+
+    /// Watches `paths` instead of the files watched so far.
+    pub fn watch(&mut self, paths: &[PathBuf]) {
+        let mut watches = self.inotify.get_ref().watches();
+
+        for (wd, _) in self.dirs.drain(..) {
+            // The watch is gone already if the directory was removed.
+            let _ = watches.remove(wd);
+        }
+
+        let mut dirs: Vec<(&Path, Vec<OsString>)> = vec![];
+        // Following a symlink too catches edits to the file it points to.
+        let targets: Vec<PathBuf> = paths
+            .iter()
+            .filter_map(|path| fs::canonicalize(path).ok())
+            .collect();
+
+        for path in paths.iter().chain(&targets) {
+            let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+                continue;
+            };
+
+            match dirs.iter_mut().find(|(watched, _)| *watched == dir) {
+                Some((_, names)) => names.push(name.to_owned()),
+                None => dirs.push((dir, vec![name.to_owned()])),
+            }
+        }
+
+        for (dir, names) in dirs {
+            match watches.add(dir, WATCH_MASK) {
+                Ok(wd) => self.dirs.push((wd, names)),
+                Err(error) => log::info!("not watching {} for changes: {error}", dir.display()),
+            }
+        }
+    }
+
+    /// Waits until a watched file changed and the changes settled.
+    pub async fn changed(&mut self) -> Result<()> {
+        loop {
+            if !self.changed {
+                self.read().await?;
+                continue;
+            }
+
+            tokio::select! {
+                result = self.read() => result?,
+                () = tokio::time::sleep(SETTLE_DELAY) => {
+                    self.changed = false;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Waits for events and reads them, noting whether any was about a watched file.
+    async fn read(&mut self) -> Result<()> {
+        let mut buffer = [0; 4096];
+        let mut guard = self.inotify.readable_mut().await?;
+        let Ok(events) = guard.try_io(|inotify| inotify.get_mut().read_events(&mut buffer)) else {
+            return Ok(());
+        };
+
+        for event in events? {
+            // Events were dropped, which may have been about watched files.
+            let overflowed = event.mask.contains(EventMask::Q_OVERFLOW);
+            let watched = self.dirs.iter().any(|(wd, names)| {
+                *wd == event.wd
+                    && event
+                        .name
+                        .is_some_and(|name| names.iter().any(|n| n == name))
+            });
+
+            self.changed |= overflowed || watched;
+        }
+
+        Ok(())
+    }
+}